@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::prelude::*;
+use std::process::{Command, Stdio};
+
+use popol::{interest, Events, Sources};
+
+/// The identifier we'll use with `popol` to figure out the source
+/// of an event: the index of the child, and whether it's its standard error.
+type Source = (usize, bool);
+
+fn main() -> io::Result<()> {
+    let scripts = [
+        "for i in 1 2 3; do echo $i; sleep 0.1; done",
+        "echo starting; sleep 0.15; echo oops >&2; echo done",
+    ];
+    let mut events = Events::new();
+    let mut sources: Sources<Source> = Sources::new();
+    let mut pipes = HashMap::new();
+    let mut children = Vec::new();
+
+    for (i, script) in scripts.iter().enumerate() {
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Reading from a pipe in blocking mode could block the other children's
+        // output, so make sure the pipes are non-blocking.
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        sources.register_nonblocking((i, false), &stdout, interest::READ)?;
+        sources.register_nonblocking((i, true), &stderr, interest::READ)?;
+
+        pipes.insert((i, false), Box::new(stdout) as Box<dyn Read>);
+        pipes.insert((i, true), Box::new(stderr) as Box<dyn Read>);
+        children.push(child);
+    }
+
+    let mut buf = [0; 1024];
+
+    while !sources.is_empty() {
+        sources.wait(&mut events)?;
+
+        for (key, event) in events.iter() {
+            // Once a child exits, its pipes are both readable and hung up until
+            // they are drained, at which point reading returns `0`.
+            if !event.should_read() {
+                continue;
+            }
+            let pipe = pipes.get_mut(key).unwrap();
+
+            loop {
+                match pipe.read(&mut buf) {
+                    Ok(0) => {
+                        sources.unregister(key);
+                        break;
+                    }
+                    Ok(n) => {
+                        let (child, stderr) = key;
+                        let name = if *stderr { "stderr" } else { "stdout" };
+
+                        for line in String::from_utf8_lossy(&buf[..n]).lines() {
+                            println!("[{}:{}] {}", child, name, line);
+                        }
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+    }
+
+    for mut child in children {
+        child.wait()?;
+    }
+    Ok(())
+}
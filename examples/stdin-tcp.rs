@@ -0,0 +1,95 @@
+use std::io;
+use std::io::prelude::*;
+use std::net;
+
+use popol::{interest, Events, Sources};
+
+/// The identifier we'll use with `popol` to figure out the source
+/// of an event.
+#[derive(Eq, PartialEq, Hash, Clone)]
+enum Source {
+    /// An event on the standard input.
+    Stdin,
+    /// An event on the connected socket.
+    Peer,
+}
+
+fn main() -> io::Result<()> {
+    let mut stream = net::TcpStream::connect("localhost:8888")?;
+    let mut events = Events::new();
+    let mut sources = Sources::new();
+
+    stream.set_nonblocking(true)?;
+    sources.register(Source::Peer, &stream, interest::READ);
+
+    // The standard input is put in non-blocking mode, so that we don't block
+    // on partial lines. Remember the previous mode, to restore it on exit.
+    let nonblocking = sources.register_stdin(Source::Stdin)?;
+
+    let result = run(&mut stream, &mut sources, &mut events);
+
+    // The standard input is shared with our parent process, eg. the shell.
+    popol::set_nonblocking(&io::stdin(), nonblocking)?;
+
+    result
+}
+
+fn run(
+    stream: &mut net::TcpStream,
+    sources: &mut Sources<Source>,
+    events: &mut Events<Source>,
+) -> io::Result<()> {
+    let mut buf = [0; 1024];
+    // Data typed, that wasn't sent to the peer yet.
+    let mut outgoing = Vec::new();
+
+    loop {
+        sources.wait(events)?;
+
+        for (key, event) in events.iter() {
+            match key {
+                // Buffer what was typed, to forward it to the peer once it's writable.
+                Source::Stdin if event.should_read() => loop {
+                    match io::stdin().read(&mut buf[..]) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => outgoing.extend_from_slice(&buf[..n]),
+
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => return Err(err),
+                    }
+                },
+                // Print what was received from the peer.
+                Source::Peer if event.should_read() => loop {
+                    match stream.read(&mut buf[..]) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => io::stdout().write_all(&buf[..n])?,
+
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => return Err(err),
+                    }
+                },
+                _ => {}
+            }
+            // Send what we can of the buffered data, since the socket is non-blocking.
+            if *key == Source::Peer && event.writable {
+                while !outgoing.is_empty() {
+                    match stream.write(&outgoing) {
+                        Ok(0) => return Ok(()),
+                        Ok(n) => {
+                            outgoing.drain(..n);
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+        }
+
+        // Only wait for the peer to be writable while there is data to send.
+        if outgoing.is_empty() {
+            sources.unset(&Source::Peer, interest::WRITE);
+        } else {
+            sources.set(&Source::Peer, interest::WRITE);
+        }
+    }
+}
@@ -1,7 +1,5 @@
 use std::{io, io::prelude::*, process, time};
 
-use popol;
-
 fn main() -> io::Result<()> {
     // Create a registry to hold I/O sources.
     let mut sources = popol::Sources::with_capacity(1);
@@ -33,7 +31,7 @@ fn main() -> io::Result<()> {
             // Read what we can from standard input and echo it.
             match io::stdin().read(&mut buf[..]) {
                 Ok(n) => io::stdout().write_all(&buf[..n])?,
-                Err(err) => panic!(err),
+                Err(err) => panic!("{}", err),
             }
         }
     }
@@ -6,7 +6,7 @@ use popol::{Events, Sources};
 
 /// The identifier we'll use with `popol` to figure out the source
 /// of an event.
-#[derive(Eq, PartialEq, Clone)]
+#[derive(Eq, PartialEq, Hash, Clone)]
 enum Source {
     /// An event from a connected peer.
     Peer(net::SocketAddr),
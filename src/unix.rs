@@ -0,0 +1,5776 @@
+//! Backend for unix platforms, based on `poll`.
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::io;
+use std::io::prelude::*;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+#[cfg(not(target_os = "linux"))]
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::time;
+
+pub use interest::Interest;
+pub use poll::Poll;
+pub use pump::Pump;
+pub use sync::{Registrar, SyncSources};
+#[cfg(target_os = "linux")]
+pub use timer::Timer;
+
+mod poll;
+mod pump;
+#[cfg(feature = "serde")]
+mod serialize;
+mod sync;
+#[cfg(target_os = "linux")]
+mod timer;
+
+/// Source readiness interest.
+pub mod interest {
+    /// Events that can be waited for.
+    pub type Interest = libc::c_short;
+
+    /// The associated file is ready to be read.
+    pub const READ: Interest = POLLIN | POLLPRI;
+    /// The associated file is ready to be written.
+    pub const WRITE: Interest = POLLOUT | libc::POLLWRBAND;
+    /// The associated file has urgent data to be read. Unlike with `READ`, normal
+    /// data being available doesn't make the file ready. Use this for files that
+    /// signal events with `POLLPRI`, eg. `sysfs` attributes and GPIO value files.
+    pub const PRI: Interest = POLLPRI;
+    /// The peer of the associated stream socket has shut down its writing half, or
+    /// closed the connection. Use this to detect half-closed connections.
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    pub const RDHUP: Interest = libc::POLLRDHUP;
+    /// The associated file is ready to be read or written, ie. `READ | WRITE`.
+    pub const ALL: Interest = READ | WRITE;
+    /// Don't wait for any events.
+    ///
+    /// Note that a source registered with this interest is still polled: hangups,
+    /// errors and invalid file descriptors are always reported.
+    pub const NONE: Interest = 0x0;
+
+    // NOTE: POLLERR, POLLNVAL and POLLHUP are ignored as *interests*, and will
+    // always be set automatically in the output events.
+
+    /// The associated file is available for read operations.
+    const POLLIN: Interest = libc::POLLIN;
+    /// There is urgent data available for read operations.
+    const POLLPRI: Interest = libc::POLLPRI;
+    /// The associated file is available for write operations.
+    const POLLOUT: Interest = libc::POLLOUT;
+
+    /// Builds interests from named events, as an alternative to combining the
+    /// constants of this module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use popol::interest;
+    ///
+    /// let events = interest::Builder::new().read().write().build();
+    /// assert_eq!(events, interest::READ | interest::WRITE);
+    ///
+    /// let events = interest::Builder::new().priority().build();
+    /// assert_eq!(events, interest::PRI);
+    /// ```
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+    pub struct Builder(Interest);
+
+    impl Builder {
+        /// Create a builder with no interests, ie. `NONE`.
+        pub fn new() -> Self {
+            Self(NONE)
+        }
+
+        /// Wait for the file to be readable. See `READ`.
+        pub fn read(self) -> Self {
+            Self(self.0 | READ)
+        }
+
+        /// Wait for the file to be writable. See `WRITE`.
+        pub fn write(self) -> Self {
+            Self(self.0 | WRITE)
+        }
+
+        /// Wait for the file to have urgent data. See `PRI`.
+        pub fn priority(self) -> Self {
+            Self(self.0 | PRI)
+        }
+
+        /// Return the interests.
+        pub fn build(self) -> Interest {
+            self.0
+        }
+    }
+}
+
+/// A source readiness event.
+///
+/// The `hangup`, `errored` and `invalid` flags can be set regardless of the
+/// interest the source was registered with, including `interest::NONE`: `poll`
+/// always reports these conditions, and they are not filtered out by `wait`.
+///
+/// Readiness is only a hint: by the time an event is handled, the underlying
+/// file may no longer be ready, eg. if it was drained elsewhere. Sources should
+/// therefore be in non-blocking mode, and `io::ErrorKind::WouldBlock` handled.
+///
+/// The `source` of an event is a copy of the registered source, taken when `wait`
+/// was called. To change the interests of a source while handling its event, use
+/// `Event::set_interest` and `Event::unset_interest` on the events passed by
+/// `Sources::for_each_event`, or `Sources::set` and `Sources::unset` with the event
+/// key. The change takes effect on the next `wait`.
+#[derive(Debug)]
+pub struct Event<'a> {
+    /// The file is writable.
+    pub writable: bool,
+    /// The file is readable. This is also set when the file has urgent data to be
+    /// read: use `priority` to tell the two apart.
+    pub readable: bool,
+    /// The file has urgent data to be read, eg. out-of-band data on a socket, or
+    /// an exceptional condition such as a `sysfs` attribute change.
+    pub priority: bool,
+    /// The peer of a stream socket has shut down its writing half, or closed the
+    /// connection, so reading will return end-of-file once buffered data is read.
+    /// Only reported if the source waits for `interest::RDHUP`, which is only
+    /// available on Linux and Android.
+    pub read_closed: bool,
+    /// The file has be disconnected. Hangups are reported whatever the interests
+    /// of the source, eg. a source only waiting to be writable can report a hangup
+    /// without being writable, so handlers should always check for them.
+    ///
+    /// A hangup doesn't mask other events: if data is still buffered when the peer
+    /// closes, the event is both `readable` and `hangup`, and the data should be read
+    /// before the source is closed.
+    pub hangup: bool,
+    /// An error has occured on the file, eg. an asynchronous socket error.
+    pub errored: bool,
+    /// The file is not valid, eg. because it was closed while still registered.
+    /// This usually means that a source wasn't unregistered before being closed.
+    pub invalid: bool,
+    /// The underlying source.
+    pub source: &'a Source,
+    /// Events returned by `poll`.
+    revents: Interest,
+    /// Whether the source is a waker.
+    waker: bool,
+    /// Number of wake-ups drained, if the source is a waker that was snoozed.
+    wakes: Option<u64>,
+    /// Interests of the registered source, if they can be changed through the event.
+    interests: Option<&'a mut Interest>,
+}
+
+impl<'a> Event<'a> {
+    /// Return the source from the underlying raw file descriptor.
+    ///
+    /// The returned value takes ownership of the file descriptor, and closes it when
+    /// dropped, while it is still owned by the registered source.
+    #[deprecated(note = "use `Event::as_fd` to borrow the file descriptor instead")]
+    pub fn source<T: FromRawFd>(&self) -> T {
+        unsafe { T::from_raw_fd(self.source.fd) }
+    }
+
+    /// Borrow the underlying file descriptor, eg. to read from it without owning it.
+    /// Returns `None` if the file descriptor is not valid, eg. because the source was
+    /// closed while still registered.
+    ///
+    /// The file descriptor must not be closed while it is borrowed, ie. the source
+    /// must not be closed between `wait` and the end of the borrow.
+    pub fn as_fd(&self) -> Option<BorrowedFd<'_>> {
+        if self.invalid || self.source.fd < 0 {
+            return None;
+        }
+        Some(unsafe { BorrowedFd::borrow_raw(self.source.fd) })
+    }
+
+    /// Accept a pending connection on a listening socket, returning it in
+    /// non-blocking mode. Returns `None` if there are no more pending connections.
+    /// Call this repeatedly until it returns `None` to accept all pending connections.
+    pub fn accept<T: FromRawFd>(&self) -> io::Result<Option<T>> {
+        #[cfg(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        ))]
+        let fd = unsafe {
+            libc::accept4(
+                self.source.fd,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            )
+        };
+        #[cfg(not(any(
+            target_os = "android",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "linux",
+            target_os = "netbsd",
+            target_os = "openbsd"
+        )))]
+        let fd = unsafe {
+            let fd = libc::accept(self.source.fd, std::ptr::null_mut(), std::ptr::null_mut());
+            if fd >= 0
+                && (libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK) < 0
+                    || libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0)
+            {
+                let err = io::Error::last_os_error();
+                libc::close(fd);
+
+                return Err(err);
+            }
+            fd
+        };
+
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+
+            return match err.kind() {
+                io::ErrorKind::WouldBlock => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(Some(unsafe { T::from_raw_fd(fd) }))
+    }
+
+    /// Read the out-of-band byte of a stream socket that has `priority` set.
+    /// Returns `None` if the event doesn't have `priority` set, or if no out-of-band
+    /// byte is pending. Returns an error of kind `io::ErrorKind::InvalidInput` if
+    /// the source isn't a stream socket.
+    ///
+    /// Note that TCP only supports a single byte of urgent data: if more is sent
+    /// before it is read, previous bytes are lost, or read as normal data. If the
+    /// socket has `SO_OOBINLINE` set, the byte is read as normal data, and this
+    /// returns `None`.
+    pub fn recv_oob(&self) -> io::Result<Option<u8>> {
+        if !self.priority {
+            return Ok(None);
+        }
+        let mut kind: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+        if unsafe {
+            libc::getsockopt(
+                self.source.fd,
+                libc::SOL_SOCKET,
+                libc::SO_TYPE,
+                &mut kind as *mut libc::c_int as *mut libc::c_void,
+                &mut len,
+            )
+        } < 0
+        {
+            let err = io::Error::last_os_error();
+
+            return match err.raw_os_error() {
+                Some(libc::ENOTSOCK) => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "out-of-band data can only be read from sockets",
+                )),
+                _ => Err(err),
+            };
+        }
+        if kind != libc::SOCK_STREAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "out-of-band data can only be read from stream sockets",
+            ));
+        }
+
+        let mut byte = 0u8;
+        let n = unsafe {
+            libc::recv(
+                self.source.fd,
+                &mut byte as *mut u8 as *mut libc::c_void,
+                1,
+                libc::MSG_OOB | libc::MSG_DONTWAIT,
+            )
+        };
+
+        if n < 0 {
+            let err = io::Error::last_os_error();
+
+            // `EINVAL` is returned when there is no urgent data, or when it is
+            // read inline.
+            return match err.raw_os_error() {
+                Some(libc::EINVAL) => Ok(None),
+                _ if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+                _ => Err(err),
+            };
+        }
+        Ok(if n == 0 { None } else { Some(byte) })
+    }
+
+    /// Return an owned copy of the event, that doesn't borrow the source. Use this
+    /// to keep events around, eg. to queue or log them.
+    pub fn flags(&self) -> EventFlags {
+        EventFlags::from(self)
+    }
+
+    /// Check whether the source should be read from. Returns true if the source is
+    /// readable, or if it was disconnected, since there may still be buffered data
+    /// to drain. Returns false if the source only has an error.
+    pub fn should_read(&self) -> bool {
+        self.readable || self.hangup
+    }
+
+    /// Check whether the source was disconnected, and has no more data to be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::io;
+    /// # fn handle(event: popol::Event<'_>) -> io::Result<()> {
+    /// if event.is_fatal() {
+    ///     // Unregister the source and drop the connection.
+    /// } else if event.has_data() {
+    ///     // Read until `io::ErrorKind::WouldBlock` is returned.
+    /// } else if event.is_closed() {
+    ///     // Unregister the source.
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_closed(&self) -> bool {
+        self.hangup && !self.readable
+    }
+
+    /// Check whether the source has data to be read, and no error.
+    pub fn has_data(&self) -> bool {
+        self.readable && !self.is_err()
+    }
+
+    /// Check whether the source can no longer be used. This is the same as `is_err`.
+    pub fn is_fatal(&self) -> bool {
+        self.is_err()
+    }
+
+    /// Check whether the event is an error. Returns true if the underlying
+    /// source is invalid, or if an error occured on it.
+    pub fn is_err(&self) -> bool {
+        self.errored || self.invalid
+    }
+}
+
+impl<'a> Event<'a> {
+    fn new(revents: Interest, source: &'a Source) -> Self {
+        #[cfg(any(target_os = "android", target_os = "linux"))]
+        let read_closed = revents & interest::RDHUP != 0;
+        #[cfg(not(any(target_os = "android", target_os = "linux")))]
+        let read_closed = false;
+
+        Self {
+            readable: revents & interest::READ != 0,
+            writable: revents & interest::WRITE != 0,
+            priority: revents & interest::PRI != 0,
+            read_closed,
+            hangup: revents & libc::POLLHUP != 0,
+            errored: revents & libc::POLLERR != 0,
+            invalid: revents & libc::POLLNVAL != 0,
+            source,
+            revents,
+            waker: false,
+            wakes: None,
+            interests: None,
+        }
+    }
+
+    /// Set event interests on the registered source of this event, in addition to
+    /// the ones already set, like `Sources::set`. The change takes effect on the next
+    /// `wait`. Returns `false`, and changes nothing, if the event wasn't passed by
+    /// `Sources::for_each_event`, or if its source was unregistered.
+    pub fn set_interest(&mut self, events: Interest) -> bool {
+        if let Some(interests) = &mut self.interests {
+            **interests |= events;
+            return true;
+        }
+        false
+    }
+
+    /// Unset event interests on the registered source of this event, keeping the
+    /// others, like `Sources::unset`. Like with `set_interest`, the change takes
+    /// effect on the next `wait`, and nothing is changed if `false` is returned.
+    pub fn unset_interest(&mut self, events: Interest) -> bool {
+        if let Some(interests) = &mut self.interests {
+            **interests &= !events;
+            return true;
+        }
+        false
+    }
+
+    /// Return the number of times the waker was woken since it was last drained,
+    /// or `None` if the source isn't a waker, or if it wasn't drained. Wakers are
+    /// drained by `Events::drain` as they are yielded, which sets the count; events
+    /// yielded by `Events::iter` leave the waker as is, and have no count.
+    pub fn waker_count(&self) -> Option<u64> {
+        self.wakes
+    }
+
+    /// Return the events returned by `poll`, as a raw bitmask. Use this to check
+    /// for events that have no flag of their own, eg. `libc::POLLRDBAND`.
+    pub fn raw(&self) -> Interest {
+        self.revents
+    }
+}
+
+impl<'a> From<&'a Source> for Event<'a> {
+    fn from(source: &'a Source) -> Self {
+        Self::new(source.revents, source)
+    }
+}
+
+/// An owned copy of a source readiness event, that doesn't borrow the source.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EventFlags {
+    /// The underlying raw file descriptor.
+    pub fd: RawFd,
+    /// The file is writable.
+    pub writable: bool,
+    /// The file is readable.
+    pub readable: bool,
+    /// The file has urgent data to be read, eg. out-of-band data on a socket, or
+    /// an exceptional condition such as a `sysfs` attribute change.
+    pub priority: bool,
+    /// The peer of a stream socket has shut down its writing half.
+    pub read_closed: bool,
+    /// The file has be disconnected.
+    pub hangup: bool,
+    /// An error has occured on the file.
+    pub errored: bool,
+    /// The file is not valid.
+    pub invalid: bool,
+    /// Number of wake-ups drained, if the source is a waker that was snoozed.
+    pub waker_count: Option<u64>,
+}
+
+impl<'a> From<&Event<'a>> for EventFlags {
+    fn from(event: &Event<'a>) -> Self {
+        Self {
+            fd: event.source.fd,
+            writable: event.writable,
+            readable: event.readable,
+            priority: event.priority,
+            read_closed: event.read_closed,
+            hangup: event.hangup,
+            errored: event.errored,
+            invalid: event.invalid,
+            waker_count: event.wakes,
+        }
+    }
+}
+
+/// How long to wait for readiness events.
+///
+/// Timeouts are passed to `poll` in milliseconds: `Infinite` maps to `-1`,
+/// `Immediate` to `0`, and `After` to the duration in milliseconds, rounded up
+/// and capped to the largest timeout `poll` accepts. Rounding up ensures that a
+/// short but non-zero timeout doesn't turn into a busy loop. Use
+/// `Sources::wait_precise` for a finer resolution.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timeout {
+    /// Block until an event is returned.
+    Infinite,
+    /// Return immediately.
+    Immediate,
+    /// Wait for at most the given duration.
+    After(time::Duration),
+}
+
+impl Timeout {
+    /// Create a timeout from milliseconds, as passed to `poll`.
+    fn from_millis(ms: libc::c_int) -> Self {
+        if ms < 0 {
+            Self::Infinite
+        } else if ms == 0 {
+            Self::Immediate
+        } else {
+            Self::After(time::Duration::from_millis(ms as u64))
+        }
+    }
+
+    /// Return the timeout in milliseconds, as passed to `poll`.
+    fn as_millis(&self) -> libc::c_int {
+        match self {
+            Self::Infinite => -1,
+            Self::Immediate => 0,
+            Self::After(duration) => {
+                let mut ms = duration.as_millis();
+                if duration.subsec_nanos() % 1_000_000 != 0 {
+                    ms += 1;
+                }
+                ms.min(libc::c_int::MAX as u128) as libc::c_int
+            }
+        }
+    }
+}
+
+impl From<time::Duration> for Timeout {
+    fn from(duration: time::Duration) -> Self {
+        Self::After(duration)
+    }
+}
+
+impl From<Option<time::Duration>> for Timeout {
+    /// `None` blocks until an event is returned.
+    fn from(duration: Option<time::Duration>) -> Self {
+        duration.map_or(Self::Infinite, Self::After)
+    }
+}
+
+impl From<()> for Timeout {
+    fn from(_: ()) -> Self {
+        Self::Infinite
+    }
+}
+
+/// Number of ready sources, by readiness category. A source may be counted in
+/// more than one category.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Counts {
+    /// Number of readable sources.
+    pub readable: usize,
+    /// Number of writable sources.
+    pub writable: usize,
+    /// Number of sources that were disconnected.
+    pub hangup: usize,
+    /// Number of sources with an error, including invalid sources.
+    pub errored: usize,
+}
+
+/// Populated by `wait` with source readiness events.
+///
+/// Events hold a snapshot of the sources that were polled, and don't borrow
+/// the `Sources` they came from. This means sources can be registered and
+/// unregistered while handling events. Events for sources unregistered after
+/// `wait` returned are still yielded, and looking these sources up with
+/// `Sources::get_mut` returns `None`.
+#[derive(Debug)]
+pub struct Events<K> {
+    /// Number of events.
+    count: usize,
+    /// Position of the first source to iterate over.
+    start: usize,
+    /// Keys of the sources polled.
+    index: Vec<K>,
+    /// Sources polled.
+    list: Vec<Source>,
+    /// Whether each source polled is a waker.
+    wakers: Vec<bool>,
+    /// Scratch space used to poll the sources, reused between waits.
+    scratch: Scratch,
+    /// Positions of the read and write halves of split sources polled as one.
+    splits: Vec<(usize, usize)>,
+}
+
+impl<K: Eq + Clone> Default for Events<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Clone> Events<K> {
+    /// Create a new empty event tracker.
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            start: 0,
+            index: vec![],
+            list: vec![],
+            wakers: vec![],
+            scratch: Scratch::new(),
+            splits: vec![],
+        }
+    }
+
+    /// Create an empty event tracker of a certain capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            count: 0,
+            start: 0,
+            index: Vec::with_capacity(cap),
+            list: Vec::with_capacity(cap),
+            wakers: vec![],
+            scratch: Scratch::new(),
+            splits: vec![],
+        }
+    }
+
+    /// Iterate over ready sources and their keys.
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            index: &self.index,
+            list: &self.list,
+            wakers: &self.wakers,
+            start: self.start,
+            pos: 0,
+        }
+    }
+
+    /// Iterate over ready sources and their keys, like `iter`, but clear the
+    /// readiness of each source as it is yielded. Unlike with `iter`, events
+    /// that were handled will not be yielded again by subsequent iterations.
+    ///
+    /// Ready wakers are snoozed as they are yielded, and the number of wake-ups
+    /// drained is returned by `Event::waker_count`. If a waker can't be snoozed,
+    /// its count is `None`; use `drain_ready` to get the error instead.
+    pub fn drain<'a>(&'a mut self) -> impl Iterator<Item = (&'a K, Event<'a>)> + 'a {
+        let (keys0, keys1) = self.index.split_at(self.start);
+        let (list0, list1) = self.list.split_at_mut(self.start);
+        let wakers = &self.wakers;
+        let count = &mut self.count;
+        let start = self.start;
+        let len = self.index.len();
+
+        keys1
+            .iter()
+            .zip(list1.iter_mut())
+            .chain(keys0.iter().zip(list0.iter_mut()))
+            .enumerate()
+            .filter(|(_, (_, d))| d.revents != 0)
+            .map(move |(pos, (key, source))| {
+                let revents = source.revents;
+                source.revents = 0;
+                *count = count.saturating_sub(1);
+
+                let ix = (start + pos) % len;
+                let mut event = Event::new(revents, source);
+                event.waker = wakers[ix];
+
+                if event.waker && event.readable {
+                    event.wakes = snooze(event.source.fd).ok();
+                }
+
+                (key, event)
+            })
+    }
+
+    /// Collect the ready sources and their keys, clearing their readiness like `drain`.
+    /// Wakers that are ready are drained, so that they are not ready on the next
+    /// `wait`, unless woken again.
+    ///
+    /// Since the returned events don't borrow anything, this is useful to react
+    /// to events by modifying the sources, or these events, while going through them.
+    pub fn drain_ready(&mut self) -> io::Result<Vec<(K, EventFlags)>> {
+        let mut ready = Vec::with_capacity(self.count);
+        self.drain_into(&mut ready)?;
+
+        Ok(ready)
+    }
+
+    /// Collect the ready sources and their keys into the given buffer, like
+    /// `drain_ready`. The buffer is cleared first, and can be reused between waits
+    /// to avoid allocating. Returns the number of events collected.
+    pub fn drain_into(&mut self, out: &mut Vec<(K, EventFlags)>) -> io::Result<usize> {
+        out.clear();
+
+        let len = self.list.len();
+
+        for pos in 0..len {
+            let ix = (self.start + pos) % len;
+            let source = &mut self.list[ix];
+
+            if source.revents == 0 {
+                continue;
+            }
+            let revents = source.revents;
+            source.revents = 0;
+            self.count = self.count.saturating_sub(1);
+
+            let mut flags = Event::new(revents, source).flags();
+            if self.wakers[ix] && flags.readable {
+                flags.waker_count = Some(snooze(flags.fd)?);
+            }
+            out.push((self.index[ix].clone(), flags));
+        }
+        Ok(out.len())
+    }
+
+    /// Iterate over the sources that were polled but aren't ready, and their keys.
+    /// Use this to find out which sources are silent.
+    pub fn idle<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a Source)> + 'a {
+        self.index
+            .iter()
+            .zip(self.list.iter())
+            .filter(|(_, s)| s.revents == 0 && s.fd >= 0)
+    }
+
+    /// Check whether any of the ready sources is a waker.
+    pub fn woken(&self) -> bool {
+        self.list
+            .iter()
+            .zip(self.wakers.iter())
+            .any(|(s, waker)| s.revents != 0 && *waker)
+    }
+
+    /// Check whether any of the ready sources is not a waker. Returns `false` if
+    /// `wait` returned only because it was woken.
+    pub fn has_io(&self) -> bool {
+        self.list
+            .iter()
+            .zip(self.wakers.iter())
+            .any(|(s, waker)| s.revents != 0 && !*waker)
+    }
+
+    /// Count ready sources by readiness category, without consuming any events.
+    pub fn counts(&self) -> Counts {
+        let mut counts = Counts::default();
+
+        for source in self.list.iter().filter(|s| s.revents != 0) {
+            let event = Event::from(source);
+
+            counts.readable += event.readable as usize;
+            counts.writable += event.writable as usize;
+            counts.hangup += event.hangup as usize;
+            counts.errored += event.is_err() as usize;
+        }
+        counts
+    }
+
+    /// Check whether the event list is empty.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Return the number of readiness events.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Initialize the events list with sources.
+    fn initialize<S>(&mut self, sources: &Sources<K, S>) {
+        self.count = 0;
+        self.start = 0;
+        self.index.clone_from(&sources.index);
+        self.list.clone_from(&sources.list);
+        self.wakers.clear();
+        self.wakers.extend(sources.state.iter().map(|s| s.waker));
+    }
+
+    /// Initialize the events list with sources, ignoring the ones that don't match
+    /// a predicate.
+    fn initialize_filtered<S>(
+        &mut self,
+        sources: &Sources<K, S>,
+        pred: impl Fn(&K, &Source) -> bool,
+    ) {
+        self.initialize(sources);
+
+        for (key, source) in self.index.iter().zip(self.list.iter_mut()) {
+            if !pred(key, source) {
+                // Negative file descriptors are ignored by `poll`.
+                source.fd = -1;
+            }
+        }
+    }
+}
+
+impl<'a, K: Eq + Clone> IntoIterator for &'a Events<K> {
+    type Item = (&'a K, Event<'a>);
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over ready sources and their keys, returned by `Events::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, K> {
+    /// Keys of the sources polled.
+    index: &'a [K],
+    /// Sources polled.
+    list: &'a [Source],
+    /// Whether each source polled is a waker.
+    wakers: &'a [bool],
+    /// Position of the first source to iterate over.
+    start: usize,
+    /// Number of sources iterated over.
+    pos: usize,
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = (&'a K, Event<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.list.len() {
+            let ix = (self.start + self.pos) % self.list.len();
+            let source = &self.list[ix];
+
+            self.pos += 1;
+
+            if source.revents != 0 {
+                let mut event = Event::from(source);
+                event.waker = self.wakers[ix];
+
+                return Some((&self.index[ix], event));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.list.len() - self.pos))
+    }
+}
+
+/// A source of readiness events, eg. a `net::TcpStream`.
+///
+/// Sources are compared and hashed by file descriptor and events only: the events
+/// reported by `poll` are transient, and don't take part in equality.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Source {
+    fd: RawFd,
+    events: Interest,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    revents: Interest,
+}
+
+// Sources are passed to `poll` as `pollfd` structures, so their layouts must match.
+const _: [(); std::mem::size_of::<libc::pollfd>()] = [(); std::mem::size_of::<Source>()];
+const _: [(); std::mem::align_of::<libc::pollfd>()] = [(); std::mem::align_of::<Source>()];
+
+impl Source {
+    /// Create a new source from a raw file descriptor, waiting for the given events.
+    /// This is a `const fn`, and can be used to declare static tables of sources.
+    pub const fn new(fd: RawFd, events: Interest) -> Self {
+        Self {
+            fd,
+            events,
+            revents: 0,
+        }
+    }
+
+    /// Return the raw file descriptor of this source.
+    pub fn fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Return the events to wait for on this source.
+    pub fn events(&self) -> Interest {
+        self.events
+    }
+
+    /// Return the events that occured on this source, as reported by `poll`. Use
+    /// this to tell apart eg. `POLLERR` from `POLLNVAL` when logging errors. Note
+    /// that this is always empty for sources yielded by `Events::drain`.
+    ///
+    /// Sources are polled through a copy held by `Events`, which is taken anew on
+    /// every wait, so this is also always empty for the registered sources, eg. as
+    /// returned by `Sources::get`, and never holds events of a previous wait.
+    pub fn revents(&self) -> Interest {
+        self.revents
+    }
+
+    /// Set events to wait for on this source.
+    pub fn set(&mut self, events: Interest) {
+        self.events |= events;
+    }
+
+    /// Unset events to wait for on this source.
+    pub fn unset(&mut self, events: Interest) {
+        self.events &= !events;
+    }
+}
+
+impl AsRawFd for Source {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        self.fd == other.fd && self.events == other.events
+    }
+}
+
+impl Eq for Source {}
+
+impl Hash for Source {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.fd.hash(state);
+        self.events.hash(state);
+    }
+}
+
+/// Source state that isn't passed to `poll`.
+#[derive(Debug, Clone, Default)]
+struct State {
+    /// Identifier of the source, unique within a set of sources.
+    id: u64,
+    /// Whether the source is edge-triggered.
+    edge: bool,
+    /// Readiness of the source after the last `poll`, if edge-triggered.
+    ready: Interest,
+    /// Events to wait for once the source is enabled, if disabled.
+    disabled: Option<Interest>,
+    /// Time of the last event of the source, or of its registration, if activity
+    /// is tracked.
+    active: Option<time::Instant>,
+    /// Whether the source is unregistered after its first event.
+    oneshot: bool,
+    /// Whether the source is the reading end of a waker.
+    waker: bool,
+    /// Which half of a source registered with `register_split` this is, if any.
+    split: Option<Half>,
+    /// The file descriptor of the source, if owned by the set. It is closed when
+    /// the source is unregistered, unless a clone of the set still owns it.
+    owned: Option<Arc<OwnedFd>>,
+}
+
+impl State {
+    /// Return the events the given source waits for, or will wait for once enabled,
+    /// if it is disabled.
+    fn interests<'a>(&'a mut self, source: &'a mut Source) -> &'a mut Interest {
+        match &mut self.disabled {
+            Some(events) => events,
+            None => &mut source.events,
+        }
+    }
+}
+
+/// Half of a source registered with `Sources::register_split`, with the identifier
+/// of the other half.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Half {
+    /// The half registered under the read key.
+    Read(u64),
+    /// The half registered under the write key.
+    Write(u64),
+}
+
+/// A handle to a registered source, for looking it up without its key.
+///
+/// A slot follows its source when it is moved, eg. when another source is
+/// unregistered, and is invalidated when its source is unregistered. Invalid slots
+/// never refer to another source: looking them up returns `None`, and a new slot
+/// can be obtained with `Sources::slot`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Slot {
+    /// Identifier of the source.
+    id: u64,
+}
+
+/// Returned when a source key is not registered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NotRegistered;
+
+impl std::fmt::Display for NotRegistered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "source is not registered")
+    }
+}
+
+impl std::error::Error for NotRegistered {}
+
+impl From<NotRegistered> for io::Error {
+    fn from(err: NotRegistered) -> Self {
+        io::Error::new(io::ErrorKind::NotFound, err)
+    }
+}
+
+/// Keeps track of sources to poll.
+#[derive(Debug, Clone)]
+pub struct Sources<K, S = RandomState> {
+    /// Tracks the keys assigned to each source.
+    index: Vec<K>,
+    /// Maps keys to their position in the list.
+    keys: HashMap<K, usize, S>,
+    /// List of sources passed to `poll`.
+    list: Vec<Source>,
+    /// State of each source, not passed to `poll`.
+    state: Vec<State>,
+    /// Maps source identifiers to their position in the list, so that slots follow
+    /// sources when they are moved.
+    slots: HashMap<u64, usize>,
+    /// Whether the position of the first source yielded rotates between waits.
+    fair: bool,
+    /// Position of the first source yielded by the next wait, if fair.
+    offset: usize,
+    /// Identifier of the next source registered.
+    next_id: u64,
+    /// Whether to check the number of sources against the open file limit on `wait`.
+    limits: bool,
+    /// Whether to record the time of the last event of each source.
+    activity: bool,
+}
+
+impl<K: Eq + Hash + Clone> Sources<K> {
+    /// Creates a new set of sources to poll.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+
+    /// Creates a new set of sources to poll, with the given capacity.
+    /// Use this if you have a lot of sources to poll.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self::with_capacity_and_hasher(cap, RandomState::new())
+    }
+
+    /// Creates a new set of sources from keys and sources, as returned by `into_parts`.
+    /// The set has no wakers, and sources have default settings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there aren't as many keys as sources.
+    pub fn from_parts(index: Vec<K>, list: Vec<Source>) -> Self {
+        assert_eq!(index.len(), list.len(), "there must be one key per source");
+
+        let mut sources = Self::with_capacity(list.len());
+        for (key, source) in index.into_iter().zip(list) {
+            sources.insert(key, Source::new(source.fd, source.events));
+        }
+        sources
+    }
+}
+
+impl<K: Eq + Hash + Clone, S: BuildHasher + Default> Default for Sources<K, S> {
+    fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<K: Eq + Hash + Clone, S: BuildHasher + Default> std::iter::FromIterator<(K, RawFd, Interest)>
+    for Sources<K, S>
+{
+    fn from_iter<I: IntoIterator<Item = (K, RawFd, Interest)>>(iter: I) -> Self {
+        let mut sources = Self::default();
+        sources.register_many(iter);
+        sources
+    }
+}
+
+impl<'a, K: Eq + Hash + Clone, S: BuildHasher> IntoIterator for &'a Sources<K, S> {
+    type Item = (&'a K, &'a Source);
+    type IntoIter = std::iter::Zip<std::slice::Iter<'a, K>, std::slice::Iter<'a, Source>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.index.iter().zip(self.list.iter())
+    }
+}
+
+impl<K: Eq + Hash + Clone, S: BuildHasher> std::ops::Index<&K> for Sources<K, S> {
+    type Output = Source;
+
+    /// Get a source by key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no source is registered under the given key.
+    fn index(&self, key: &K) -> &Source {
+        self.get(key)
+            .expect("no source is registered under the given key")
+    }
+}
+
+impl<K: Eq + Hash + Clone, S: BuildHasher> Extend<(K, RawFd, Interest)> for Sources<K, S> {
+    fn extend<I: IntoIterator<Item = (K, RawFd, Interest)>>(&mut self, iter: I) {
+        self.register_many(iter);
+    }
+}
+
+impl<K: Eq + Hash + Clone, S: BuildHasher> Sources<K, S> {
+    /// Creates a new set of sources to poll, using the given hasher to hash keys.
+    /// Use this if keys are influenced by untrusted input, or to use a faster hasher.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_capacity_and_hasher(0, hasher)
+    }
+
+    /// Creates a new set of sources to poll, with the given capacity, using the given
+    /// hasher to hash keys.
+    pub fn with_capacity_and_hasher(cap: usize, hasher: S) -> Self {
+        Self {
+            index: Vec::with_capacity(cap),
+            keys: HashMap::with_capacity_and_hasher(cap, hasher),
+            list: Vec::with_capacity(cap),
+            state: Vec::with_capacity(cap),
+            slots: HashMap::with_capacity(cap),
+            fair: false,
+            offset: 0,
+            next_id: 0,
+            limits: cfg!(debug_assertions),
+            activity: false,
+        }
+    }
+
+    /// Return the hasher used to hash keys.
+    pub fn hasher(&self) -> &S {
+        self.keys.hasher()
+    }
+
+    /// Split the set into its keys and sources, in polling order. This is a low-level
+    /// escape hatch, eg. to convert the set to another representation. Wakers are
+    /// not preserved: their sources are kept, but are no longer known to be wakers,
+    /// and should be re-created when rebuilding a set with `from_parts`. Other source
+    /// settings, eg. whether a source is disabled or edge-triggered, are lost.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set owns the file descriptor of any source, ie. one registered
+    /// with `register_owned`, since it would be closed, and the returned source
+    /// would refer to a closed, or reused, file descriptor. Unregister these sources
+    /// first.
+    pub fn into_parts(self) -> (Vec<K>, Vec<Source>) {
+        assert!(
+            self.state.iter().all(|s| s.owned.is_none()),
+            "sources with owned file descriptors can't be split into parts"
+        );
+        (self.index, self.list)
+    }
+
+    /// Reserve capacity for at least `additional` more wakers. Use this if
+    /// you are going to create a lot of wakers up front.
+    pub fn reserve_wakers(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    /// Reserve capacity for at least `additional` more sources. Use this ahead of
+    /// registering a lot of sources, eg. during a burst of connections, and
+    /// `compact` to reclaim the memory afterwards.
+    pub fn reserve(&mut self, additional: usize) {
+        self.index.reserve(additional);
+        self.keys.reserve(additional);
+        self.list.reserve(additional);
+        self.state.reserve(additional);
+        self.slots.reserve(additional);
+    }
+
+    /// Shrink the capacity of the set as much as possible. Use this to reclaim
+    /// memory after a lot of sources were unregistered.
+    pub fn compact(&mut self) {
+        self.index.shrink_to_fit();
+        self.keys.shrink_to_fit();
+        self.list.shrink_to_fit();
+        self.state.shrink_to_fit();
+        self.slots.shrink_to_fit();
+    }
+
+    /// Shrink the capacity of the set like `compact`, and sort the sources by key,
+    /// undoing the reordering caused by unregistering sources. Slots follow the
+    /// sources that are moved.
+    pub fn compact_sorted(&mut self)
+    where
+        K: Ord,
+    {
+        let mut order = (0..self.index.len()).collect::<Vec<_>>();
+        order.sort_by(|a, b| self.index[*a].cmp(&self.index[*b]));
+
+        self.index = order.iter().map(|ix| self.index[*ix].clone()).collect();
+        self.list = order.iter().map(|ix| self.list[*ix]).collect();
+        self.state = order.iter().map(|ix| self.state[*ix].clone()).collect();
+
+        self.keys.clear();
+        self.slots.clear();
+        for (ix, (key, state)) in self.index.iter().zip(self.state.iter()).enumerate() {
+            self.keys.insert(key.clone(), ix);
+            self.slots.insert(state.id, ix);
+        }
+        self.compact();
+    }
+
+    /// Return the number of registered sources, including wakers.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Return whether the source registry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Return the number of sources that can be registered without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.list.capacity()
+    }
+
+    /// Return the list of sources as the `pollfd` structures passed to `poll`.
+    pub fn as_pollfd_slice(&self) -> &[libc::pollfd] {
+        unsafe {
+            std::slice::from_raw_parts(self.list.as_ptr() as *const libc::pollfd, self.list.len())
+        }
+    }
+
+    /// Register a new source, with the given key, and wait for the specified events.
+    ///
+    /// Regular files are always reported as ready by `poll`, so registering one
+    /// will cause `wait` to return immediately, every time. Use `try_register` to
+    /// guard against this.
+    ///
+    /// The source must be kept open for as long as it is registered. Use
+    /// `register_owned` to hand it over to the set instead.
+    ///
+    /// If a source is already registered under the given key, it is replaced, keeping
+    /// its position in the list, and its slots are invalidated. This applies to all
+    /// the ways of registering a source.
+    pub fn register(&mut self, key: K, fd: &impl AsRawFd, events: Interest) {
+        self.insert(key, Source::new(fd.as_raw_fd(), events));
+    }
+
+    /// Register several sources at once, like `register`. Capacity is reserved up
+    /// front, based on the lower bound of the iterator's size hint.
+    pub fn register_many(&mut self, sources: impl IntoIterator<Item = (K, RawFd, Interest)>) {
+        let sources = sources.into_iter();
+        let (additional, _) = sources.size_hint();
+
+        self.reserve(additional);
+
+        for (key, fd, events) in sources {
+            self.insert(key, Source::new(fd, events));
+        }
+    }
+
+    /// Register a new source in a disabled state, keeping a position in the list
+    /// for it. The source will wait for the specified events once enabled with
+    /// `enable`. Note that like with `interest::NONE`, hangups and errors are still
+    /// reported for disabled sources.
+    pub fn register_disabled(&mut self, key: K, fd: &impl AsRawFd, events: Interest) {
+        let ix = self.insert(key, Source::new(fd.as_raw_fd(), interest::NONE));
+        self.state[ix].disabled = Some(events);
+    }
+
+    /// Enable a disabled source, restoring the events it waited for. Returns
+    /// `false` if the source was not found.
+    pub fn enable(&mut self, key: &K) -> bool {
+        if let Some(ix) = self.find(key) {
+            if let Some(events) = self.state[ix].disabled.take() {
+                self.list[ix].events = events;
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Disable a source, so that it stops waiting for events until enabled with
+    /// `enable`. Returns `false` if the source was not found.
+    pub fn disable(&mut self, key: &K) -> bool {
+        if let Some(ix) = self.find(key) {
+            if self.state[ix].disabled.is_none() {
+                self.state[ix].disabled = Some(self.list[ix].events);
+                self.list[ix].events = interest::NONE;
+            }
+            return true;
+        }
+        false
+    }
+
+    /// Register a source under two keys: one waiting for it to be readable, and the
+    /// other waiting for it to be writable. This allows read and write interests
+    /// to be set, unset and handled independently, through their own key. Hangups
+    /// and errors are reported under both keys.
+    ///
+    /// The file descriptor is polled once, for the interests of both keys, and each
+    /// key is only reported the events it waits for. If either key is unregistered
+    /// or registered again, the other one goes on as a regular source.
+    pub fn register_split(&mut self, read_key: K, write_key: K, fd: &impl AsRawFd) {
+        let fd = fd.as_raw_fd();
+
+        let r = self.insert(read_key, Source::new(fd, interest::READ));
+        let w = self.insert(write_key, Source::new(fd, interest::WRITE));
+
+        self.state[r].split = Some(Half::Read(self.state[w].id));
+        self.state[w].split = Some(Half::Write(self.state[r].id));
+    }
+
+    /// Register a new source, like `register`, and return a slot for it.
+    pub fn register_slot(&mut self, key: K, fd: &impl AsRawFd, events: Interest) -> Slot {
+        let ix = self.insert(key, Source::new(fd.as_raw_fd(), events));
+        Slot {
+            id: self.state[ix].id,
+        }
+    }
+
+    /// Return a slot for the source with the given key.
+    pub fn slot(&self, key: &K) -> Option<Slot> {
+        self.find(key).map(|ix| Slot {
+            id: self.state[ix].id,
+        })
+    }
+
+    /// Get a source by slot. Returns `None` if the slot is no longer valid.
+    pub fn get_by_slot(&self, slot: Slot) -> Option<&Source> {
+        self.slots.get(&slot.id).map(|ix| &self.list[*ix])
+    }
+
+    /// Set the events to poll for on a source identified by a slot. Returns `false`
+    /// if the slot is no longer valid.
+    pub fn set_by_slot(&mut self, slot: Slot, events: Interest) -> bool {
+        if let Some(&ix) = self.slots.get(&slot.id) {
+            *self.state[ix].interests(&mut self.list[ix]) |= events;
+            return true;
+        }
+        false
+    }
+
+    /// Register the program's standard input, and wait for it to be readable.
+    ///
+    /// Standard input is usually in blocking mode, which means that reading from it
+    /// can block even after it was reported readable, eg. if only part of a line was
+    /// typed. To avoid this, it is put in non-blocking mode, and whether it was
+    /// previously in non-blocking mode is returned. Since the standard input is
+    /// usually shared with the parent process, eg. the shell, restore its mode with
+    /// `set_nonblocking` before exiting. The terminal mode, eg. line buffering and
+    /// echo, is left untouched.
+    pub fn register_stdin(&mut self, key: K) -> io::Result<bool> {
+        let nonblocking = set_nonblocking(&io::stdin(), true)?;
+        self.register(key, &io::stdin(), interest::READ);
+
+        Ok(nonblocking)
+    }
+
+    /// Register the program's standard output, and wait for it to be writable.
+    ///
+    /// Unlike with `register_stdin`, the mode of the standard output is left
+    /// untouched, since `print!` and friends fail when it would block. Use
+    /// `set_nonblocking` to change it, and restore it before exiting.
+    pub fn register_stdout(&mut self, key: K) {
+        self.register(key, &io::stdout(), interest::WRITE);
+    }
+
+    /// Register the program's standard error, and wait for it to be writable.
+    /// See `register_stdout` for caveats.
+    pub fn register_stderr(&mut self, key: K) {
+        self.register(key, &io::stderr(), interest::WRITE);
+    }
+
+    /// Register a new source, like `register`, that is unregistered once it has an
+    /// event. The event is yielded as usual, since events hold a snapshot of the
+    /// sources, but the source isn't polled again. Slots of the source are
+    /// invalidated.
+    pub fn register_oneshot(&mut self, key: K, fd: &impl AsRawFd, events: Interest) {
+        let ix = self.insert(key, Source::new(fd.as_raw_fd(), events));
+        self.state[ix].oneshot = true;
+    }
+
+    /// Register a new source, like `register`, and take ownership of its file
+    /// descriptor. The file descriptor is kept open for as long as the source is
+    /// registered, and closed when it is unregistered, or when the set is dropped.
+    /// Clones of the set share ownership of the file descriptor.
+    ///
+    /// Use this to make sure a source isn't closed while it is registered, in which
+    /// case its file descriptor could be reused by an unrelated file.
+    pub fn register_owned(&mut self, key: K, fd: OwnedFd, events: Interest) {
+        let ix = self.insert(key, Source::new(fd.as_raw_fd(), events));
+        self.state[ix].owned = Some(Arc::new(fd));
+    }
+
+    /// Register a new source, like `register`, but first put it in non-blocking mode.
+    ///
+    /// A readiness event doesn't guarantee that a subsequent read or write won't
+    /// block: eg. data can be discarded after it was reported, or another reader
+    /// can consume it first. Reading from or writing to a source in blocking mode
+    /// may then block the whole event loop, so non-blocking mode is strongly
+    /// recommended for all sources.
+    ///
+    /// This also works with pipes, eg. the `ChildStdout` and `ChildStderr` of a child
+    /// process. Once the child exits, its pipes are reported as both readable and hung
+    /// up until drained, after which reading them returns `0`. See the `children`
+    /// example.
+    pub fn register_nonblocking(
+        &mut self,
+        key: K,
+        fd: &impl AsRawFd,
+        events: Interest,
+    ) -> io::Result<()> {
+        set_nonblocking(fd, true)?;
+        self.register(key, fd, events);
+
+        Ok(())
+    }
+
+    /// Register a new source, like `register`, but return an error of kind
+    /// `io::ErrorKind::InvalidInput` if the source is a regular file.
+    pub fn try_register(&mut self, key: K, fd: &impl AsRawFd, events: Interest) -> io::Result<()> {
+        let fd = fd.as_raw_fd();
+        let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+
+        if unsafe { libc::fstat(fd, stat.as_mut_ptr()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let stat = unsafe { stat.assume_init() };
+
+        if stat.st_mode & libc::S_IFMT == libc::S_IFREG {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "regular files are always ready and can't be polled",
+            ));
+        }
+        self.insert(key, Source::new(fd, events));
+
+        Ok(())
+    }
+
+    /// Unregister a source, given its key, and return it. Returns `None` if no
+    /// source is registered under the given key.
+    pub fn unregister(&mut self, key: &K) -> Option<Source> {
+        let ix = self.find(key)?;
+
+        Some(self.remove(ix))
+    }
+
+    /// Unregister a source, given its file descriptor, and return its key. Returns
+    /// `None` if no source has the given file descriptor. If several sources have
+    /// it, eg. when registered with `register_split`, only the first one in the
+    /// list is unregistered.
+    pub fn unregister_fd(&mut self, fd: RawFd) -> Option<K> {
+        let ix = self.list.iter().position(|s| s.fd == fd)?;
+        let key = self.index[ix].clone();
+
+        self.remove(ix);
+
+        Some(key)
+    }
+
+    /// Unregister all sources, including wakers, keeping the allocated capacity.
+    /// Settings such as fairness are kept, and slots of unregistered sources
+    /// remain invalid.
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.keys.clear();
+        self.list.clear();
+        self.state.clear();
+        self.slots.clear();
+        self.offset = 0;
+    }
+
+    /// Move all sources from `other` into this set, so that they can be polled
+    /// together, leaving `other` empty. Wakers registered with `other` keep working,
+    /// but slots obtained from `other` are invalidated.
+    /// If any key of `other` is already registered, returns an error of kind
+    /// `io::ErrorKind::AlreadyExists` and leaves both sets unchanged.
+    pub fn merge<T>(&mut self, other: &mut Sources<K, T>) -> io::Result<()> {
+        if other.index.iter().any(|k| self.keys.contains_key(k)) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "source key is already registered",
+            ));
+        }
+        let offset = self.list.len();
+
+        self.keys
+            .extend(other.keys.drain().map(|(k, ix)| (k, ix + offset)));
+        self.index.append(&mut other.index);
+        self.list.append(&mut other.list);
+        self.state.append(&mut other.state);
+
+        let mut ids = HashMap::new();
+
+        for (ix, state) in self.state.iter_mut().enumerate().skip(offset) {
+            ids.insert(state.id, self.next_id);
+            state.id = self.next_id;
+            self.slots.insert(state.id, ix);
+            self.next_id += 1;
+        }
+        // Split sources refer to their other half by identifier.
+        for state in &mut self.state[offset..] {
+            state.split = match state.split {
+                Some(Half::Read(id)) => ids.get(&id).map(|id| Half::Read(*id)),
+                Some(Half::Write(id)) => ids.get(&id).map(|id| Half::Write(*id)),
+                None => None,
+            };
+        }
+        other.slots.clear();
+        other.offset = 0;
+
+        Ok(())
+    }
+
+    /// Change the key of a registered source, keeping its position in the list.
+    /// Returns `false` if no source was found for the old key, or if a source is
+    /// already registered under the new key.
+    pub fn rekey(&mut self, old: &K, new: K) -> bool {
+        if new != *old && self.keys.contains_key(&new) {
+            return false;
+        }
+        if let Some(ix) = self.keys.remove(old) {
+            self.keys.insert(new.clone(), ix);
+            self.index[ix] = new;
+
+            return true;
+        }
+        false
+    }
+
+    /// Set event interests on a source identified by its key, in addition to the
+    /// ones already set. Returns `false` if the source was not found. Disabled
+    /// sources get these interests once enabled.
+    pub fn set(&mut self, key: &K, events: Interest) -> bool {
+        if let Some(ix) = self.find(key) {
+            *self.state[ix].interests(&mut self.list[ix]) |= events;
+            return true;
+        }
+        false
+    }
+
+    /// Replace the events to poll for on a source, and return the events it polled
+    /// for until now. Returns `None` if the source was not found. Unlike `set`,
+    /// which adds to the existing events, this replaces them. The events of disabled
+    /// sources are the ones they will have once enabled.
+    pub fn modify(&mut self, key: &K, events: Interest) -> Option<Interest> {
+        let ix = self.find(key)?;
+
+        Some(std::mem::replace(
+            self.state[ix].interests(&mut self.list[ix]),
+            events,
+        ))
+    }
+
+    /// Unset event interests on a source, keeping the others. Returns `false` if
+    /// the source was not found. Note that hangups and errors are still reported
+    /// for sources with no interests set. Like with `set`, disabled sources lose
+    /// these interests once enabled.
+    pub fn unset(&mut self, key: &K, events: Interest) -> bool {
+        if let Some(ix) = self.find(key) {
+            *self.state[ix].interests(&mut self.list[ix]) &= !events;
+            return true;
+        }
+        false
+    }
+
+    /// Set the events to poll for on a source, like `set`, but return an error
+    /// if no source is registered under the given key.
+    pub fn try_set(&mut self, key: &K, events: Interest) -> Result<(), NotRegistered> {
+        if self.set(key, events) {
+            Ok(())
+        } else {
+            Err(NotRegistered)
+        }
+    }
+
+    /// Unset event interests on a source, like `unset`, but return an error
+    /// if no source is registered under the given key.
+    pub fn try_unset(&mut self, key: &K, events: Interest) -> Result<(), NotRegistered> {
+        if self.unset(key, events) {
+            Ok(())
+        } else {
+            Err(NotRegistered)
+        }
+    }
+
+    /// Capture the event interests of all sources, with their keys. The interests
+    /// of disabled sources are the ones they will have once enabled. Use this with
+    /// `apply_events` to restore interests later.
+    pub fn events_snapshot(&self) -> Vec<(K, Interest)> {
+        self.index
+            .iter()
+            .zip(self.list.iter().zip(self.state.iter()))
+            .map(|(key, (source, state))| (key.clone(), state.disabled.unwrap_or(source.events)))
+            .collect()
+    }
+
+    /// Replace the event interests of sources, eg. from a snapshot taken with
+    /// `events_snapshot`. Keys that aren't registered are ignored, and disabled
+    /// sources stay disabled.
+    pub fn apply_events(&mut self, snapshot: impl IntoIterator<Item = (K, Interest)>) {
+        for (key, events) in snapshot {
+            if let Some(ix) = self.find(&key) {
+                *self.state[ix].interests(&mut self.list[ix]) = events;
+            }
+        }
+    }
+
+    /// Check whether a source is registered under the given key.
+    pub fn contains(&self, key: &K) -> bool {
+        self.keys.contains_key(key)
+    }
+
+    /// Iterate over the registered sources and their keys, in polling order,
+    /// including wakers.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Source)> {
+        self.into_iter()
+    }
+
+    /// Iterate over the keys of the registered sources, in polling order, including
+    /// wakers.
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.index.iter()
+    }
+
+    /// Get a source by key, without borrowing the set mutably.
+    pub fn get(&self, key: &K) -> Option<&Source> {
+        self.find(key).map(|ix| &self.list[ix])
+    }
+
+    /// Get a source by key.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut Source> {
+        self.find(key).map(move |ix| &mut self.list[ix])
+    }
+
+    /// Wait for readiness events on the given list of sources. If no event
+    /// is returned within the given timeout, returns an error of kind `io::ErrorKind::TimedOut`.
+    ///
+    /// The timeout is anything that converts into a `Timeout`, eg. a `Duration`, or
+    /// `None` to block until an event is returned.
+    ///
+    /// If there are no sources to poll, returns immediately without events, instead
+    /// of waiting for the timeout to expire. This applies to all the ways of waiting.
+    pub fn wait_timeout(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), io::Error> {
+        self.wait_ms(events, timeout.into().as_millis())
+    }
+
+    /// Wait for readiness events like `wait_timeout`, until the given deadline. If the
+    /// deadline has already passed, the sources are polled without blocking, and
+    /// an error of kind `io::ErrorKind::TimedOut` is returned unless a source is ready.
+    pub fn wait_until(
+        &mut self,
+        events: &mut Events<K>,
+        deadline: time::Instant,
+    ) -> Result<(), io::Error> {
+        let now = time::Instant::now();
+
+        self.wait_timeout(events, deadline.saturating_duration_since(now))
+    }
+
+    /// Wait for readiness events on the given list of sources, with a timeout
+    /// in milliseconds, as passed to `poll`: `-1` blocks until an event is returned,
+    /// `0` returns immediately, and a positive value waits for at most that
+    /// many milliseconds. If no event is returned within the timeout, returns an
+    /// error of kind `io::ErrorKind::TimedOut`.
+    pub fn wait_ms(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: libc::c_int,
+    ) -> Result<(), io::Error> {
+        events.initialize(self);
+
+        self.wait_initialized(events, Timeout::from_millis(timeout))
+    }
+
+    /// Wait for readiness events on the sources matching the given predicate,
+    /// ignoring the others. Otherwise behaves like `wait_timeout`.
+    ///
+    /// Sources that don't match are neither polled nor yielded, not even by
+    /// `Events::idle`. If no source matches, there are no sources to poll, and this
+    /// returns immediately without events.
+    pub fn wait_filtered(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+        pred: impl Fn(&K, &Source) -> bool,
+    ) -> Result<(), io::Error> {
+        events.initialize_filtered(self, pred);
+
+        if events.list.iter().all(|s| s.fd < 0) {
+            return Ok(());
+        }
+        self.wait_initialized(events, timeout.into())
+    }
+
+    fn wait_initialized(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: Timeout,
+    ) -> Result<(), io::Error> {
+        self.wait_with(events, timeout, |list, scratch, timeout| {
+            poll_uninterrupted(timeout, |t| poll(list, scratch, t.as_millis()))
+        })
+    }
+
+    /// Wait for readiness events on initialized events, using the given function
+    /// to poll the sources with the given scratch space and timeout.
+    fn wait_with(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: Timeout,
+        poll: impl FnOnce(&mut [Source], &mut Scratch, Timeout) -> io::Result<usize>,
+    ) -> Result<(), io::Error> {
+        if events.list.is_empty() {
+            return Ok(());
+        }
+        if self.limits {
+            self.check_limits()?;
+        }
+        self.prepare(events);
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!("wait", sources = events.list.len(), ?timeout).entered();
+
+        let result = poll(&mut events.list, &mut events.scratch, timeout)
+            .map_err(|e| poll_error(e, events.list.len()))?;
+
+        #[cfg(feature = "tracing")]
+        trace_polled(&events.list, result);
+
+        self.complete(events, result);
+
+        // Edge-triggered sources that are still ready make `poll` return, but their
+        // readiness is suppressed: this isn't a timeout, even if there are no events.
+        if result == 0 {
+            Err(io::ErrorKind::TimedOut.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Prepare initialized events to be polled.
+    fn prepare(&mut self, events: &mut Events<K>) {
+        if self.fair && !events.list.is_empty() {
+            events.start = self.offset % events.list.len();
+            self.offset = events.start + 1;
+        }
+        events.splits.clear();
+
+        if self.state.iter().any(|s| s.split.is_some()) {
+            self.merge_splits(events);
+        }
+    }
+
+    /// Merge the write half of each split source into its read half, so that the
+    /// file descriptor is only polled once.
+    fn merge_splits(&self, events: &mut Events<K>) {
+        for (w, state) in self.state.iter().enumerate() {
+            let r = match state.split {
+                Some(Half::Write(read)) => match self.slots.get(&read) {
+                    Some(&r) => r,
+                    None => continue,
+                },
+                _ => continue,
+            };
+            // The read half may have been registered again, or filtered out.
+            if self.state[r].split != Some(Half::Read(state.id))
+                || events.list[w].fd < 0
+                || events.list[r].fd != events.list[w].fd
+            {
+                continue;
+            }
+            events.list[r].events |= events.list[w].events;
+            // Negative file descriptors are ignored by `poll`.
+            events.list[w].fd = -1;
+            events.splits.push((r, w));
+        }
+    }
+
+    /// Report the events of merged split sources under both their keys, each key
+    /// getting the events it waits for, and hangups and errors. Returns the number
+    /// of ready sources.
+    fn fan_out(&self, events: &mut Events<K>) -> usize {
+        const ALWAYS: Interest = libc::POLLHUP | libc::POLLERR | libc::POLLNVAL;
+
+        for &(r, w) in events.splits.iter() {
+            let revents = events.list[r].revents;
+
+            events.list[r] = self.list[r];
+            events.list[r].revents = revents & (self.list[r].events | ALWAYS);
+            events.list[w] = self.list[w];
+            events.list[w].revents = revents & (self.list[w].events | ALWAYS);
+        }
+        events.list.iter().filter(|s| s.revents != 0).count()
+    }
+
+    /// Process events after they were polled, given the number of ready sources.
+    fn complete(&mut self, events: &mut Events<K>, mut result: usize) {
+        if !events.splits.is_empty() {
+            result = self.fan_out(events);
+        }
+        if self.state.iter().any(|s| s.edge) {
+            result = self.trigger(events);
+        }
+        if self.activity && result > 0 {
+            let now = time::Instant::now();
+
+            for (state, source) in self.state.iter_mut().zip(events.list.iter()) {
+                if source.revents != 0 {
+                    state.active = Some(now);
+                }
+            }
+        }
+
+        // Remove the ready oneshot sources, starting from the end of the list, so
+        // that sources are only moved after they are checked.
+        for ix in (0..self.state.len().min(events.list.len())).rev() {
+            if self.state[ix].oneshot && events.list[ix].revents != 0 {
+                self.remove(ix);
+            }
+        }
+        events.count = result;
+    }
+
+    /// Wait for readiness events on the given list of sources. If the wait is
+    /// interrupted by a signal, it is resumed.
+    pub fn wait(&mut self, events: &mut Events<K>) -> Result<(), io::Error> {
+        // As far as I know, `poll` should never return `0` if the timeout
+        // value is `-1`.
+        self.wait_ms(events, -1)
+    }
+
+    /// Wait for readiness events like `wait_timeout`, but replace the signal mask of
+    /// the calling thread with `sigmask` for the duration of the wait, using `ppoll`.
+    /// Since the mask is replaced atomically, signals can be blocked outside of the
+    /// wait and only delivered during it, without the race inherent to self-pipes.
+    ///
+    /// Unlike with `wait_timeout`, the wait isn't resumed when interrupted by a
+    /// signal: an error of kind `io::ErrorKind::Interrupted` is returned instead.
+    #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+    pub fn wait_with_sigmask(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+        sigmask: &libc::sigset_t,
+    ) -> Result<(), io::Error> {
+        let timeout = timeout.into();
+        events.initialize(self);
+
+        self.wait_with(events, timeout, |list, _, timeout| {
+            ppoll(list, timeout, Some(sigmask))
+        })
+    }
+
+    /// Wait for readiness events like `wait_timeout`, but with a timeout resolution
+    /// of a nanosecond instead of a millisecond, using `ppoll`. Use this for timeouts
+    /// that aren't a whole number of milliseconds.
+    #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+    pub fn wait_precise(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), io::Error> {
+        let timeout = timeout.into();
+        events.initialize(self);
+
+        self.wait_with(events, timeout, |list, _, timeout| {
+            poll_uninterrupted(timeout, |t| ppoll(list, t, None))
+        })
+    }
+
+    /// Wait for readiness events like `wait_timeout`, and collect them into the given
+    /// buffer with `Events::drain_into`. Returns the number of events collected.
+    ///
+    /// The buffer is cleared before waiting, so that it is empty if the wait fails,
+    /// or times out.
+    pub fn wait_into(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+        out: &mut Vec<(K, EventFlags)>,
+    ) -> io::Result<usize> {
+        out.clear();
+
+        self.wait_timeout(events, timeout)?;
+        events.drain_into(out)
+    }
+
+    /// Wait for readiness events like `wait_timeout`, and call the given function
+    /// with each event. Since events are copied out, the function is also passed
+    /// the sources, which can be freely modified, eg. to register new sources.
+    ///
+    /// Wakers are reset before their event is passed to the function, like with
+    /// `Events::drain_ready`.
+    pub fn dispatch(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+        mut f: impl FnMut(&mut Self, K, EventFlags),
+    ) -> Result<(), io::Error> {
+        self.wait_timeout(events, timeout)?;
+
+        for (key, event) in events.iter() {
+            let mut flags = event.flags();
+
+            if event.waker && event.readable {
+                flags.waker_count = Some(snooze(flags.fd)?);
+            }
+            f(self, key.clone(), flags);
+        }
+        Ok(())
+    }
+
+    /// Call the given function with each ready source of the given events and its
+    /// key, in the order of `Events::iter`. Unlike with `Events::iter`, the events can
+    /// change the interests of their registered source with `Event::set_interest`
+    /// and `Event::unset_interest`, without looking it up again by key.
+    pub fn for_each_event(&mut self, events: &Events<K>, mut f: impl FnMut(&K, Event<'_>)) {
+        for (key, event) in events.iter() {
+            let mut event: Event<'_> = event;
+
+            if let Some(ix) = self.find(key) {
+                event.interests = Some(self.state[ix].interests(&mut self.list[ix]));
+            }
+            f(key, event);
+        }
+    }
+
+    /// Make a source edge-triggered or level-triggered. Returns `false` if the
+    /// source was not found.
+    ///
+    /// By default, sources are level-triggered: an event is returned by every
+    /// `wait` call for as long as the source is ready. Edge-triggered sources only
+    /// return an event when they become ready, for example when new data arrives.
+    /// The source must then be fully drained, eg. read until `WouldBlock`, otherwise
+    /// it stays ready and `wait` keeps returning immediately, without events for
+    /// that source. This isn't a timeout: if no other source is ready, `wait` returns
+    /// successfully without events.
+    pub fn set_edge(&mut self, key: &K, edge: bool) -> bool {
+        if let Some(ix) = self.find(key) {
+            self.state[ix].edge = edge;
+            self.state[ix].ready = 0;
+
+            return true;
+        }
+        false
+    }
+
+    /// Check that the number of registered sources doesn't exceed the process' soft
+    /// limit on open files (`RLIMIT_NOFILE`), past which `poll` fails. Returns an error
+    /// of kind `io::ErrorKind::InvalidInput` if it does.
+    pub fn check_limits(&self) -> io::Result<()> {
+        check_limits(self.list.len())
+    }
+
+    /// Enable or disable calling `check_limits` before every wait. This costs a system
+    /// call per wait, and is only enabled by default in debug builds.
+    pub fn set_check_limits(&mut self, enabled: bool) {
+        self.limits = enabled;
+    }
+
+    /// Enable or disable recording the time of the last event of each source, for
+    /// use with `last_active` and `reap_idle`. When enabled, sources are considered
+    /// active when registered, or when tracking is enabled.
+    pub fn set_track_activity(&mut self, enabled: bool) {
+        let now = time::Instant::now();
+
+        for state in self.state.iter_mut() {
+            state.active = if enabled { Some(now) } else { None };
+        }
+        self.activity = enabled;
+    }
+
+    /// Return the time of the last event of a source, or of its registration if
+    /// it had no events. Returns `None` if the source was not found, or if activity
+    /// isn't tracked.
+    pub fn last_active(&self, key: &K) -> Option<time::Instant> {
+        self.find(key).and_then(|ix| self.state[ix].active)
+    }
+
+    /// Unregister the sources that had no events for longer than `max_idle`, and
+    /// return their keys. Does nothing if activity isn't tracked.
+    pub fn reap_idle(&mut self, max_idle: time::Duration) -> Vec<K> {
+        let idle = self
+            .index
+            .iter()
+            .zip(self.state.iter())
+            .filter(|(_, s)| matches!(s.active, Some(t) if t.elapsed() > max_idle))
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+
+        for key in &idle {
+            self.unregister(key);
+        }
+        idle
+    }
+
+    /// Unregister sources whose file descriptors aren't open, and return their keys.
+    /// The sources are polled without blocking to find them, without affecting
+    /// the events of the next wait. Use this to clean up sources that may have
+    /// been closed while registered, eg. after a fork.
+    pub fn prune_invalid(&mut self) -> io::Result<Vec<K>> {
+        let mut list = self.list.clone();
+        poll_uninterrupted(Timeout::Immediate, |t| {
+            poll(&mut list, &mut Scratch::new(), t.as_millis())
+        })
+        .map_err(|e| poll_error(e, list.len()))?;
+
+        let invalid = self
+            .index
+            .iter()
+            .zip(list.iter())
+            .filter(|(_, s)| s.revents & libc::POLLNVAL != 0)
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+
+        for key in &invalid {
+            self.unregister(key);
+        }
+        Ok(invalid)
+    }
+
+    /// Rotate the position of the first event yielded after each wait, so that
+    /// every source gets a turn at being handled first. Use this if not all events
+    /// are handled after every wait, to prevent sources from being starved.
+    pub fn set_fair(&mut self, fair: bool) {
+        self.fair = fair;
+        self.offset = 0;
+    }
+
+    /// Suppress readiness that was already reported for edge-triggered sources,
+    /// and return the number of ready sources.
+    fn trigger(&mut self, events: &mut Events<K>) -> usize {
+        let mut count = 0;
+
+        for (source, state) in events.list.iter_mut().zip(self.state.iter_mut()) {
+            if state.edge && source.fd >= 0 {
+                let ready = source.revents;
+
+                source.revents &= !state.ready;
+                state.ready = ready;
+            }
+            if source.revents != 0 {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        self.keys.get(key).copied()
+    }
+
+    /// Unregister the source at the given position, and return it.
+    fn remove(&mut self, ix: usize) -> Source {
+        self.keys.remove(&self.index[ix]);
+        self.slots.remove(&self.state[ix].id);
+        let source = self.list.swap_remove(ix);
+
+        self.index.swap_remove(ix);
+        self.state.swap_remove(ix);
+
+        // The last source was moved into the removed source's position.
+        if let Some(moved) = self.index.get(ix) {
+            if let Some(i) = self.keys.get_mut(moved) {
+                *i = ix;
+            }
+            self.slots.insert(self.state[ix].id, ix);
+        }
+        source
+    }
+
+    /// Insert a source under the given key, replacing the source already registered
+    /// under that key, if any, and return its position.
+    fn insert(&mut self, key: K, source: Source) -> usize {
+        let state = State {
+            id: self.next_id,
+            active: if self.activity {
+                Some(time::Instant::now())
+            } else {
+                None
+            },
+            ..State::default()
+        };
+        self.next_id += 1;
+
+        if let Some(ix) = self.find(&key) {
+            self.slots.remove(&self.state[ix].id);
+            self.slots.insert(state.id, ix);
+            self.list[ix] = source;
+            self.state[ix] = state;
+
+            return ix;
+        }
+        let ix = self.index.len();
+
+        self.keys.insert(key.clone(), ix);
+        self.slots.insert(state.id, ix);
+        self.index.push(key);
+        self.list.push(source);
+        self.state.push(state);
+
+        ix
+    }
+}
+
+/// Wait for readiness events on several sets of sources at once, each with its
+/// own events. Returns once any of the sources is ready, after which every set of
+/// events holds the readiness events of its sources. If no event is returned within
+/// the given timeout, returns an error of kind `io::ErrorKind::TimedOut`.
+///
+/// Use this to wait on independent sets of sources without merging them.
+///
+/// # Examples
+///
+/// ```
+/// use std::os::unix::net::UnixStream;
+/// use std::io::Write;
+/// use std::time::Duration;
+///
+/// use popol::{interest, Events, Sources};
+///
+/// fn main() -> std::io::Result<()> {
+///     let (mut writer, reader) = UnixStream::pair()?;
+///     let (_other_writer, other_reader) = UnixStream::pair()?;
+///
+///     let mut clients = Sources::new();
+///     let mut peers = Sources::new();
+///     let mut client_events = Events::new();
+///     let mut peer_events = Events::new();
+///
+///     clients.register("client", &reader, interest::READ);
+///     peers.register("peer", &other_reader, interest::READ);
+///     writer.write_all(&[1])?;
+///
+///     popol::wait_any(
+///         &mut [
+///             (&mut clients, &mut client_events),
+///             (&mut peers, &mut peer_events),
+///         ],
+///         Duration::from_secs(1),
+///     )?;
+///
+///     assert_eq!(client_events.len(), 1);
+///     assert!(peer_events.is_empty());
+///
+///     Ok(())
+/// }
+/// ```
+pub fn wait_any<K: Eq + Hash + Clone, S: BuildHasher>(
+    sets: &mut [(&mut Sources<K, S>, &mut Events<K>)],
+    timeout: impl Into<Timeout>,
+) -> io::Result<()> {
+    let mut list = Vec::new();
+    let mut limits = false;
+
+    for (sources, events) in sets.iter_mut() {
+        events.initialize(sources);
+        sources.prepare(events);
+        list.extend_from_slice(&events.list);
+        limits |= sources.limits;
+    }
+    if list.is_empty() {
+        return Ok(());
+    }
+    if limits {
+        check_limits(list.len())?;
+    }
+    let timeout = timeout.into();
+
+    #[cfg(feature = "tracing")]
+    let _span = tracing::trace_span!("wait_any", sources = list.len(), ?timeout).entered();
+
+    let mut scratch = Scratch::new();
+    let result = poll_uninterrupted(timeout, |t| poll(&mut list, &mut scratch, t.as_millis()))
+        .map_err(|e| poll_error(e, list.len()))?;
+
+    #[cfg(feature = "tracing")]
+    trace_polled(&list, result);
+
+    let mut polled = list.iter();
+
+    for (sources, events) in sets.iter_mut() {
+        for (source, result) in events.list.iter_mut().zip(polled.by_ref()) {
+            source.revents = result.revents;
+        }
+        let result = events.list.iter().filter(|s| s.revents != 0).count();
+
+        sources.complete(events, result);
+    }
+
+    // Like with `Sources::wait`, suppressed edge-triggered readiness isn't a timeout.
+    if result == 0 {
+        Err(io::ErrorKind::TimedOut.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Set or unset non-blocking mode on a file descriptor, and return whether it
+/// was previously in non-blocking mode, so that it can be restored.
+///
+/// Readiness events don't guarantee that an operation won't block, so sources
+/// should be in non-blocking mode. This works with any kind of file descriptor,
+/// including pipes and terminals, unlike eg. `TcpStream::set_nonblocking`.
+///
+/// # Examples
+///
+/// ```
+/// use std::io;
+///
+/// let stdin = io::stdin();
+/// let nonblocking = popol::set_nonblocking(&stdin, true)?;
+///
+/// // ... Read from standard input until `io::ErrorKind::WouldBlock` is returned ...
+///
+/// // Restore the previous mode, since it's shared with the parent process.
+/// popol::set_nonblocking(&stdin, nonblocking)?;
+/// # Ok::<(), io::Error>(())
+/// ```
+pub fn set_nonblocking(fd: &impl AsRawFd, nonblocking: bool) -> io::Result<bool> {
+    let fd = fd.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let new = if nonblocking {
+        flags | libc::O_NONBLOCK
+    } else {
+        flags & !libc::O_NONBLOCK
+    };
+    if new != flags && unsafe { libc::fcntl(fd, libc::F_SETFL, new) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(flags & libc::O_NONBLOCK != 0)
+}
+
+/// Wait for readiness events like `poll`, using `ppoll`. If a signal mask is given,
+/// it replaces the signal mask of the calling thread during the wait.
+#[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+fn ppoll(
+    list: &mut [Source],
+    timeout: Timeout,
+    sigmask: Option<&libc::sigset_t>,
+) -> io::Result<usize> {
+    let timespec = match timeout {
+        Timeout::Infinite => None,
+        Timeout::Immediate => Some(time::Duration::from_secs(0)),
+        Timeout::After(duration) => Some(duration),
+    }
+    .map(|duration| {
+        let mut timespec: libc::timespec = unsafe { std::mem::zeroed() };
+        timespec.tv_sec = duration.as_secs().min(libc::time_t::MAX as u64) as libc::time_t;
+        timespec.tv_nsec = duration.subsec_nanos() as _;
+        timespec
+    });
+
+    let result = unsafe {
+        libc::ppoll(
+            list.as_mut_ptr() as *mut libc::pollfd,
+            list.len() as libc::nfds_t,
+            timespec
+                .as_ref()
+                .map_or(std::ptr::null(), |t| t as *const libc::timespec),
+            sigmask.map_or(std::ptr::null(), |m| m as *const libc::sigset_t),
+        )
+    };
+
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Trace the result of `poll`, and the events of every ready source.
+#[cfg(feature = "tracing")]
+fn trace_polled(list: &[Source], result: usize) {
+    tracing::trace!(result, "polled");
+
+    if tracing::enabled!(tracing::Level::TRACE) {
+        for source in list.iter().filter(|s| s.revents != 0) {
+            tracing::trace!(fd = source.fd, revents = source.revents, "ready");
+        }
+    }
+}
+
+/// Wait for readiness events with the given polling function, retrying when
+/// interrupted by a signal. The time spent before the interruption is deducted from
+/// the timeout, and if the timeout has expired, no sources are ready.
+fn poll_uninterrupted(
+    timeout: Timeout,
+    mut poll: impl FnMut(Timeout) -> io::Result<usize>,
+) -> io::Result<usize> {
+    let start = time::Instant::now();
+    let mut remaining = timeout;
+
+    loop {
+        match poll(remaining) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                if let Timeout::After(duration) = timeout {
+                    match duration.checked_sub(start.elapsed()) {
+                        Some(left) if left > time::Duration::from_secs(0) => {
+                            remaining = Timeout::After(left);
+                        }
+                        _ => return Ok(0),
+                    }
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Scratch space used by `poll`. With `libc`, sources are passed to `poll` as they are.
+#[cfg(not(feature = "rustix"))]
+#[derive(Debug)]
+struct Scratch;
+
+#[cfg(not(feature = "rustix"))]
+impl Scratch {
+    fn new() -> Self {
+        Self
+    }
+}
+
+/// Scratch space used by `poll`. With `rustix`, sources are copied to a list of
+/// `PollFd`, which is kept to avoid allocating it on every wait.
+#[cfg(feature = "rustix")]
+type Scratch = Vec<rustix::event::PollFd<'static>>;
+
+/// Wait for readiness events on a list of sources, and return the number of
+/// ready sources.
+#[cfg(not(feature = "rustix"))]
+fn poll(list: &mut [Source], _scratch: &mut Scratch, timeout: libc::c_int) -> io::Result<usize> {
+    let result = unsafe {
+        libc::poll(
+            list.as_mut_ptr() as *mut libc::pollfd,
+            list.len() as libc::nfds_t,
+            timeout,
+        )
+    };
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result as usize)
+    }
+}
+
+/// Wait for readiness events on a list of sources, and return the number of
+/// ready sources. Only `poll` itself is called through `rustix`.
+#[cfg(feature = "rustix")]
+fn poll(list: &mut [Source], fds: &mut Scratch, timeout: libc::c_int) -> io::Result<usize> {
+    use rustix::event::{PollFd, PollFlags, Timespec};
+    use std::os::unix::io::BorrowedFd;
+
+    // Negative file descriptors are ignored by `poll`, and can't be borrowed, so they
+    // are skipped, and get no events. Other file descriptors may have been closed
+    // since they were registered: the borrowed file descriptors are only passed to
+    // `poll`, which reports closed ones as invalid, and don't outlive this call,
+    // since the list is cleared below.
+    fds.clear();
+    fds.extend(list.iter().filter(|s| s.fd >= 0).map(|s| {
+        let fd = unsafe { BorrowedFd::borrow_raw(s.fd) };
+        PollFd::from_borrowed_fd(fd, PollFlags::from_bits_retain(s.events as u16))
+    }));
+    let timeout = if timeout < 0 {
+        None
+    } else {
+        Some(Timespec {
+            tv_sec: (timeout / 1000).into(),
+            tv_nsec: ((timeout % 1000) * 1_000_000).into(),
+        })
+    };
+    let result = rustix::event::poll(fds, timeout.as_ref());
+
+    if result.is_ok() {
+        for (source, fd) in list.iter_mut().filter(|s| s.fd >= 0).zip(fds.iter()) {
+            source.revents = fd.revents().bits() as Interest;
+        }
+        for source in list.iter_mut().filter(|s| s.fd < 0) {
+            source.revents = 0;
+        }
+    }
+    fds.clear();
+
+    Ok(result?)
+}
+
+/// Check that `nfds` sources can be polled without exceeding the open file limit.
+fn check_limits(nfds: usize) -> io::Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if nfds as libc::rlim_t > limit.rlim_cur {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "too many sources to poll: {} exceeds the open file limit of {}",
+                nfds, limit.rlim_cur
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Add context to an error returned by `poll` on `nfds` sources.
+fn poll_error(err: io::Error, nfds: usize) -> io::Error {
+    match err.raw_os_error() {
+        Some(libc::EINVAL) => check_limits(nfds).err().unwrap_or(err),
+        Some(libc::EFAULT) => io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid address for the {} sources to poll", nfds),
+        ),
+        _ => err,
+    }
+}
+
+/// Wakers are used to wake up `wait`.
+///
+/// A waker is registered like any other source, and its events are yielded in
+/// registration order, alongside the events of other sources. Since `wait` returns
+/// all ready sources at once, a waker that is woken constantly can't prevent
+/// events on other sources from being returned.
+///
+/// Dropping all handles to a waker closes its source, which should then be
+/// unregistered: until it is, it is reported as `invalid` by `wait`.
+///
+/// On Linux, wakers are backed by an `eventfd`, which is both read from and written
+/// to. On other platforms, they are backed by a pair of connected unix sockets.
+pub struct Waker {
+    #[cfg(target_os = "linux")]
+    reader: Arc<std::fs::File>,
+    #[cfg(not(target_os = "linux"))]
+    reader: Arc<UnixStream>,
+    #[cfg(not(target_os = "linux"))]
+    writer: UnixStream,
+}
+
+impl Waker {
+    /// Create a new `Waker`.
+    ///
+    /// # Examples
+    ///
+    /// Wake a `wait` call from another thread.
+    ///
+    /// ```
+    /// fn main() -> Result<(), Box<dyn std::error::Error>> {
+    ///     use std::thread;
+    ///     use std::time::Duration;
+    ///     use std::sync::Arc;
+    ///
+    ///     use popol::{Sources, Events, Waker};
+    ///
+    ///     const WAKER: &'static str = "waker";
+    ///
+    ///     let mut events = Events::new();
+    ///     let mut sources = Sources::new();
+    ///
+    ///     // Create a waker and keep it alive until the end of the program, so that
+    ///     // the reading end doesn't get closed.
+    ///     let waker = Arc::new(Waker::new(&mut sources, WAKER)?);
+    ///     let _waker = waker.clone();
+    ///
+    ///     let handle = thread::spawn(move || {
+    ///         thread::sleep(Duration::from_millis(160));
+    ///
+    ///         // Wake up popol on the main thread.
+    ///         _waker.wake().expect("waking shouldn't fail");
+    ///     });
+    ///
+    ///     // Wait to be woken up by the other thread. Otherwise, time out.
+    ///     sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+    ///
+    ///     assert!(!events.is_empty(), "There should be at least one event selected");
+    ///
+    ///     let mut events = events.iter();
+    ///     let (key, event) = events.next().unwrap();
+    ///
+    ///     assert!(key == &WAKER, "The event is triggered by the waker");
+    ///     assert!(event.readable, "The event is readable");
+    ///     assert!(events.next().is_none(), "There was only one event");
+    ///
+    ///     handle.join().unwrap();
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn new<K: Eq + Hash + Clone, S: BuildHasher>(
+        sources: &mut Sources<K, S>,
+        key: K,
+    ) -> io::Result<Waker> {
+        let waker = Self::open()?;
+        let fd = waker.reader_fd();
+
+        let ix = sources.insert(key, Source::new(fd, interest::READ));
+        sources.state[ix].waker = true;
+
+        Ok(waker)
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "rustix")))]
+    fn open() -> io::Result<Waker> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Waker {
+            reader: Arc::new(unsafe { std::fs::File::from_raw_fd(fd) }),
+        })
+    }
+
+    #[cfg(all(target_os = "linux", feature = "rustix"))]
+    fn open() -> io::Result<Waker> {
+        use rustix::event::{eventfd, EventfdFlags};
+
+        let fd = eventfd(0, EventfdFlags::NONBLOCK | EventfdFlags::CLOEXEC)?;
+
+        Ok(Waker {
+            reader: Arc::new(std::fs::File::from(fd)),
+        })
+    }
+
+    #[cfg(all(not(target_os = "linux"), not(feature = "rustix")))]
+    fn open() -> io::Result<Waker> {
+        let (writer, reader) = UnixStream::pair()?;
+
+        reader.set_nonblocking(true)?;
+        writer.set_nonblocking(true)?;
+
+        Ok(Waker {
+            reader: Arc::new(reader),
+            writer,
+        })
+    }
+
+    #[cfg(all(not(target_os = "linux"), feature = "rustix"))]
+    fn open() -> io::Result<Waker> {
+        use rustix::io::{fcntl_setfd, ioctl_fionbio, FdFlags};
+        use rustix::net::{socketpair, AddressFamily, SocketFlags, SocketType};
+
+        // Not all platforms support creating sockets with these flags, eg. macOS.
+        let (writer, reader) = socketpair(
+            AddressFamily::UNIX,
+            SocketType::STREAM,
+            SocketFlags::empty(),
+            None,
+        )?;
+
+        for fd in [&writer, &reader] {
+            fcntl_setfd(fd, FdFlags::CLOEXEC)?;
+            ioctl_fionbio(fd, true)?;
+        }
+
+        Ok(Waker {
+            reader: Arc::new(UnixStream::from(reader)),
+            writer: UnixStream::from(writer),
+        })
+    }
+
+    /// Write a wake-up to the waker.
+    #[cfg(target_os = "linux")]
+    fn signal(&self) -> io::Result<()> {
+        (&*self.reader).write_all(&1u64.to_ne_bytes())
+    }
+
+    /// Write a wake-up to the waker.
+    #[cfg(not(target_os = "linux"))]
+    fn signal(&self) -> io::Result<()> {
+        (&self.writer).write_all(&[0x1])
+    }
+
+    /// Wake up a waker. Causes `popol::wait` to return with a readiness
+    /// event for this waker.
+    ///
+    /// If the waker's buffer is full, eg. because it isn't being snoozed, a wake-up
+    /// is already pending, and this returns successfully without writing anything.
+    /// With an `eventfd`, this only happens after about `u64::MAX` wake-ups.
+    /// The count returned by `Event::waker_count` is then lower than the number of
+    /// calls to `wake`.
+    pub fn wake(&self) -> io::Result<()> {
+        use io::ErrorKind::*;
+
+        match self.signal() {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == WouldBlock => Ok(()),
+            Err(e) if e.kind() == Interrupted => self.wake(),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Create a new handle to the same waker, eg. to wake it from another thread.
+    /// Waking any of the handles wakes the waker. The handles share the reading
+    /// end of the waker, which stays open until all handles are dropped.
+    pub fn try_clone(&self) -> io::Result<Waker> {
+        Ok(Waker {
+            reader: self.reader.clone(),
+            #[cfg(not(target_os = "linux"))]
+            writer: self.writer.try_clone()?,
+        })
+    }
+
+    /// Return the file descriptor of the reading end of the waker. This is the
+    /// file descriptor registered with `Sources`.
+    pub fn reader_fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+
+    /// Return the file descriptor of the writing end of the waker. With an `eventfd`,
+    /// this is the same as the reading end.
+    pub fn writer_fd(&self) -> RawFd {
+        #[cfg(target_os = "linux")]
+        let fd = self.reader.as_raw_fd();
+        #[cfg(not(target_os = "linux"))]
+        let fd = self.writer.as_raw_fd();
+
+        fd
+    }
+
+    /// Drain the waker's receive buffer, so that it is no longer ready, and
+    /// return the number of wake-ups that were pending.
+    pub fn snooze(&self) -> io::Result<u64> {
+        snooze(self.reader.as_raw_fd())
+    }
+}
+
+/// Reset the counter of an `eventfd` waker, and return the number of wake-ups
+/// that were pending.
+#[cfg(target_os = "linux")]
+fn snooze(fd: RawFd) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+        if n == buf.len() as isize {
+            return Ok(u64::from_ne_bytes(buf));
+        } else if n >= 0 {
+            return Ok(0);
+        } else {
+            let err = io::Error::last_os_error();
+
+            match err.kind() {
+                io::ErrorKind::WouldBlock => return Ok(0),
+                io::ErrorKind::Interrupted => continue,
+                _ => return Err(err),
+            }
+        }
+    }
+}
+
+/// Drain the receive buffer of a waker, given the file descriptor of its reading
+/// end, and return the number of wake-ups that were pending.
+#[cfg(not(target_os = "linux"))]
+fn snooze(fd: RawFd) -> io::Result<u64> {
+    let mut buf = [0u8; 4096];
+    let mut count = 0;
+
+    loop {
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+
+        if n > 0 {
+            count += n as u64;
+        } else if n == 0 {
+            return Ok(count);
+        } else {
+            let err = io::Error::last_os_error();
+
+            match err.kind() {
+                io::ErrorKind::WouldBlock => return Ok(count),
+                io::ErrorKind::Interrupted => continue,
+                _ => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Return the file descriptors of the wakers registered with a set.
+    fn wakers<K, S>(sources: &Sources<K, S>) -> Vec<RawFd> {
+        sources
+            .list
+            .iter()
+            .zip(sources.state.iter())
+            .filter(|(_, state)| state.waker)
+            .map(|(source, _)| source.fd)
+            .collect()
+    }
+
+    #[test]
+    fn test_readable() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (writer1, reader1) = UnixStream::pair()?;
+        let (writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        for reader in &[&reader0, &reader1, &reader2] {
+            reader.set_nonblocking(true)?;
+        }
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        {
+            let err = sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(events.is_empty());
+        }
+
+        let tests = &mut [
+            (&writer0, &reader0, "reader0", 0x1_u8),
+            (&writer1, &reader1, "reader1", 0x2_u8),
+            (&writer2, &reader2, "reader2", 0x3_u8),
+        ];
+
+        for (mut writer, mut reader, key, byte) in tests.iter_mut() {
+            let mut buf = [0u8; 1];
+
+            assert!(matches!(
+                reader.read(&mut buf[..]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock
+            ));
+
+            writer.write_all(&[*byte])?;
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            assert!(!events.is_empty());
+
+            let mut events = events.iter();
+            let (k, event) = events.next().unwrap();
+
+            assert_eq!(&k, &key);
+            assert!(event.readable && !event.writable && !event.errored && !event.hangup);
+            assert!(events.next().is_none());
+
+            assert_eq!(reader.read(&mut buf[..])?, 1);
+            assert_eq!(&buf[..], &[*byte]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty() -> io::Result<()> {
+        let mut events: Events<()> = Events::new();
+        let mut sources = Sources::new();
+
+        sources
+            .wait_timeout(&mut events, time::Duration::from_millis(1))
+            .expect("no error if nothing registered");
+
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register((), &io::stdin(), interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+
+        assert_eq!(sources.len(), 1);
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_threaded() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (writer1, reader1) = UnixStream::pair()?;
+        let (writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let readers = &[&reader0, &reader1, &reader2];
+
+        for reader in readers {
+            reader.set_nonblocking(true)?;
+        }
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(8));
+
+            for writer in &mut [&writer1, &writer2, &writer0] {
+                writer.write_all(&[1]).unwrap();
+                writer.write_all(&[2]).unwrap();
+            }
+        });
+
+        let mut closed = vec![];
+        while closed.len() < readers.len() {
+            sources.wait_timeout(&mut events, Duration::from_millis(64))?;
+
+            for (key, event) in events.iter() {
+                assert!(event.readable);
+                assert!(!event.writable);
+                assert!(!event.errored);
+
+                if event.hangup {
+                    closed.push(*key);
+                    continue;
+                }
+
+                let mut buf = [0u8; 2];
+                let mut reader = match *key {
+                    "reader0" => &reader0,
+                    "reader1" => &reader1,
+                    "reader2" => &reader2,
+                    _ => unreachable!(),
+                };
+                let n = reader.read(&mut buf[..])?;
+
+                assert_eq!(n, 2);
+                assert_eq!(&buf[..], &[1, 2]);
+            }
+        }
+        handle.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unregister() -> io::Result<()> {
+        use std::collections::HashSet;
+
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+        let (writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        for reader in &[&reader0, &reader1, &reader2] {
+            reader.set_nonblocking(true)?;
+        }
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        {
+            let err = sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .unwrap_err();
+
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+            assert!(events.is_empty());
+        }
+
+        {
+            writer1.write_all(&[0x0])?;
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let (key, _) = events.iter().next().unwrap();
+
+            assert_eq!(key, &"reader1");
+        }
+
+        // Unregister.
+        {
+            sources.unregister(&"reader1");
+            writer1.write_all(&[0x0])?;
+
+            sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .ok();
+            assert!(events.iter().next().is_none());
+
+            for w in &mut [&writer0, &writer1, &writer2] {
+                w.write_all(&[0])?;
+            }
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let keys = events.iter().map(|(k, _)| k).collect::<HashSet<_>>();
+
+            assert!(keys.contains(&"reader0"));
+            assert!(!keys.contains(&"reader1"));
+            assert!(keys.contains(&"reader2"));
+
+            sources.unregister(&"reader0");
+
+            for w in &mut [&writer0, &writer1, &writer2] {
+                w.write_all(&[0])?;
+            }
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let keys = events.iter().map(|(k, _)| k).collect::<HashSet<_>>();
+
+            assert!(!keys.contains(&"reader0"));
+            assert!(!keys.contains(&"reader1"));
+            assert!(keys.contains(&"reader2"));
+
+            sources.unregister(&"reader2");
+
+            for w in &mut [&writer0, &writer1, &writer2] {
+                w.write_all(&[0])?;
+            }
+
+            sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .ok();
+
+            assert!(events.is_empty());
+        }
+
+        // Re-register.
+        {
+            sources.register("reader0", &reader0, interest::READ);
+            writer0.write_all(&[0])?;
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let (key, _) = events.iter().next().unwrap();
+
+            assert_eq!(key, &"reader0");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        for reader in &[&reader0, &reader1] {
+            reader.set_nonblocking(true)?;
+        }
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::NONE);
+
+        {
+            writer0.write_all(&[0])?;
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let (key, _) = events.iter().next().unwrap();
+            assert_eq!(key, &"reader0");
+
+            sources.unset(key, interest::READ);
+            writer0.write_all(&[0])?;
+
+            sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .ok();
+            assert!(events.iter().next().is_none());
+        }
+
+        {
+            writer1.write_all(&[0])?;
+
+            sources
+                .wait_timeout(&mut events, Duration::from_millis(1))
+                .ok();
+            assert!(events.iter().next().is_none());
+
+            sources.set(&"reader1", interest::READ);
+            writer1.write_all(&[0])?;
+
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let (key, _) = events.iter().next().unwrap();
+            assert_eq!(key, &"reader1");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_interest() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let mut fds = [0; 2];
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        // Writing to a pipe with no readers is an error.
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let (reader1, writer1) = unsafe {
+            (
+                UnixStream::from_raw_fd(fds[0]),
+                UnixStream::from_raw_fd(fds[1]),
+            )
+        };
+
+        sources.register("hangup", &reader0, interest::NONE);
+        sources.register("errored", &writer1, interest::NONE);
+        sources.register("invalid", &RawFd::MAX, interest::NONE);
+
+        sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .ok();
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["invalid"]);
+
+        drop(writer0);
+        drop(reader1);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.len(), 3);
+
+        for (key, event) in events.iter() {
+            assert!(!event.readable && !event.writable);
+
+            match *key {
+                "hangup" => assert!(event.hangup && !event.errored && !event.invalid),
+                "errored" => assert!(event.errored && !event.invalid),
+                "invalid" => assert!(event.invalid && !event.hangup && !event.errored),
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_regular_file() -> io::Result<()> {
+        let path = std::env::temp_dir().join(format!("popol-{}", std::process::id()));
+        let file = std::fs::File::create(&path)?;
+        std::fs::remove_file(&path)?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        let err = sources
+            .try_register("file", &file, interest::READ)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(sources.is_empty());
+
+        let (_writer, reader) = UnixStream::pair()?;
+        sources.try_register("stream", &reader, interest::READ)?;
+        assert_eq!(sources.len(), 1);
+
+        // Regular files are always ready.
+        sources.register("file", &file, interest::ALL);
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (key, event) = events.iter().next().unwrap();
+        assert_eq!(key, &"file");
+        assert!(event.readable && event.writable);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rekey() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+
+        assert!(sources.rekey(&"reader0", "session0"));
+        assert!(!sources.rekey(&"reader0", "session1"));
+        assert_eq!(sources.index, vec!["session0", "reader1"]);
+
+        writer0.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let (key, _) = events.iter().next().unwrap();
+        assert_eq!(key, &"session0");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drain() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+
+        writer0.write_all(&[0])?;
+        writer1.write_all(&[0])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.iter().count(), 2);
+        assert_eq!(events.len(), 2);
+
+        let (key, event) = events.drain().next().unwrap();
+        assert_eq!(key, &"reader0");
+        assert!(event.readable);
+        assert_eq!(event.source.revents, 0);
+        assert_eq!(events.len(), 1);
+
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader1"]);
+
+        assert_eq!(events.drain().count(), 1);
+        assert_eq!(events.iter().count(), 0);
+        assert_eq!(events.len(), 0);
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_counts() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (writer1, reader1) = UnixStream::pair()?;
+        let (_writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::ALL);
+
+        writer0.write_all(&[0])?;
+        drop(writer1);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(
+            events.counts(),
+            Counts {
+                readable: 2,
+                writable: 1,
+                hangup: 1,
+                errored: 0,
+            }
+        );
+        assert_eq!(events.iter().count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_ms() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let err = sources.wait_ms(&mut events, 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let err = sources.wait_ms(&mut events, 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer.write_all(&[0])?;
+        sources.wait_ms(&mut events, 0)?;
+        assert_eq!(events.len(), 1);
+
+        sources.wait_ms(&mut events, -1)?;
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_snapshot() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("a", &reader, interest::READ);
+        sources.register("b", &reader, interest::WRITE);
+        sources.register_disabled("c", &reader, interest::READ);
+
+        let snapshot = sources.events_snapshot();
+        assert_eq!(
+            snapshot,
+            vec![
+                ("a", interest::READ),
+                ("b", interest::WRITE),
+                ("c", interest::READ)
+            ]
+        );
+
+        sources.set(&"a", interest::WRITE);
+        sources.unset(&"b", interest::WRITE);
+        sources.unregister(&"c");
+        sources.register("c", &reader, interest::NONE);
+
+        sources.apply_events(snapshot);
+        assert_eq!(sources.get_mut(&"a").unwrap().events, interest::READ);
+        assert_eq!(sources.get_mut(&"b").unwrap().events, interest::WRITE);
+        assert_eq!(sources.get_mut(&"c").unwrap().events, interest::READ);
+
+        sources.disable(&"c");
+        sources.apply_events(vec![("c", interest::WRITE), ("d", interest::READ)]);
+        assert_eq!(sources.get_mut(&"c").unwrap().events, interest::NONE);
+
+        sources.enable(&"c");
+        assert_eq!(sources.get_mut(&"c").unwrap().events, interest::WRITE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parts() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+
+        let (index, list) = sources.into_parts();
+        assert_eq!(index, vec!["waker", "reader", "writer"]);
+        assert_eq!(list[0].fd, waker.reader_fd());
+        assert_eq!(list[2].events, interest::WRITE);
+
+        let mut sources = Sources::from_parts(index, list);
+        assert!(wakers(&sources).is_empty());
+        assert_eq!(sources.find(&"writer"), Some(2));
+
+        sources.unregister(&"writer");
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(
+            events.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec!["reader"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "owned file descriptors")]
+    fn test_parts_owned() {
+        let (writer, _reader) = UnixStream::pair().unwrap();
+        let mut sources = Sources::new();
+
+        sources.register_owned("writer", OwnedFd::from(writer), interest::WRITE);
+        sources.into_parts();
+    }
+
+    #[test]
+    fn test_event_as_fd() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(b"hi")?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        for (_, event) in events.iter() {
+            let fd = event.as_fd().unwrap();
+            let mut stream = std::fs::File::from(fd.try_clone_to_owned()?);
+            let mut buf = [0; 1];
+
+            stream.read_exact(&mut buf)?;
+            assert_eq!(&buf, b"h");
+        }
+        // The original source is still open.
+        let mut buf = [0; 1];
+        (&reader).read_exact(&mut buf)?;
+        assert_eq!(&buf, b"i");
+
+        // Invalid file descriptors can't be borrowed.
+        let source = Source::new(reader.as_raw_fd(), interest::READ);
+        assert!(Event::new(libc::POLLNVAL, &source).as_fd().is_none());
+        let source = Source::new(-1, interest::READ);
+        assert!(Event::new(libc::POLLIN, &source).as_fd().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_closed_source() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        // Use a high file descriptor, so that it isn't reused by another test running
+        // concurrently once closed.
+        let fd = unsafe { libc::fcntl(reader.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 900) };
+        assert!(fd >= 900, "{}", io::Error::last_os_error());
+
+        sources.register("closed", &fd, interest::READ);
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (key, event) = events.iter().next().unwrap();
+        assert_eq!(*key, "closed");
+        assert!(event.invalid);
+        assert!(!event.errored);
+        assert!(event.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_invalid() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        // See `test_closed_source`, which uses file descriptors from 900.
+        let fd = unsafe { libc::fcntl(reader.as_raw_fd(), libc::F_DUPFD_CLOEXEC, 950) };
+        assert!(fd >= 950, "{}", io::Error::last_os_error());
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("closed", &fd, interest::READ);
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+
+        assert_eq!(sources.prune_invalid()?, vec!["closed"]);
+        assert_eq!(sources.keys().collect::<Vec<_>>(), vec![&"reader"]);
+        assert!(sources.prune_invalid()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_len() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+        let (_writer2, reader2) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, time::Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(events.len(), 0);
+        assert_eq!(events.iter().count(), 0);
+
+        writer0.write_all(&[1])?;
+        writer1.write_all(&[1])?;
+        sources.wait_timeout(&mut events, time::Duration::from_secs(1))?;
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events.iter().count(), events.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_eq() {
+        use std::collections::HashSet;
+
+        let mut ready = Source::new(3, interest::READ);
+        ready.revents = libc::POLLIN;
+
+        assert_eq!(ready, Source::new(3, interest::READ));
+        assert_ne!(ready, Source::new(3, interest::WRITE));
+        assert_ne!(ready, Source::new(4, interest::READ));
+
+        let set = [ready, Source::new(3, interest::READ)]
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_stale_revents() -> io::Result<()> {
+        let (mut writer, mut reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(&[1])?;
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert!(events.iter().next().unwrap().1.readable);
+        assert_eq!(sources[&"reader"].revents(), 0);
+
+        reader.read_exact(&mut [0])?;
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(events.iter().next().is_none());
+        assert!(events.list.iter().all(|s| s.revents() == 0));
+        assert_eq!(sources[&"reader"].revents(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hangup_readable() -> io::Result<()> {
+        let (mut writer, mut reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(b"bye")?;
+        drop(writer);
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.readable);
+        assert!(event.hangup);
+
+        let mut buf = vec![];
+        reader.read_to_end(&mut buf)?;
+        assert_eq!(buf, b"bye");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unregister_fd() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        assert_eq!(sources.unregister_fd(reader.as_raw_fd()), Some("reader"));
+        assert_eq!(sources.unregister_fd(reader.as_raw_fd()), None);
+        assert_eq!(sources[&"writer"].fd(), writer.as_raw_fd());
+
+        assert_eq!(sources.unregister_fd(waker.reader_fd()), Some("waker"));
+        assert!(wakers(&sources).is_empty());
+        assert_eq!(sources.keys().collect::<Vec<_>>(), vec![&"writer"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+
+        assert_eq!(sources[&"reader"].fd(), reader.as_raw_fd());
+        assert_eq!(sources[&"writer"].events(), interest::WRITE);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "no source is registered")]
+    fn test_index_missing() {
+        let sources = Sources::<&str>::new();
+        let _ = sources[&"missing"];
+    }
+
+    #[test]
+    fn test_event_raw() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.raw() & libc::POLLIN != 0);
+        assert_eq!(event.raw() & libc::POLLOUT, 0);
+        assert_eq!(event.raw(), event.source.revents());
+
+        // The events are kept when the readiness of the source is cleared.
+        let (_, event) = events.drain().next().unwrap();
+        assert!(event.raw() & libc::POLLIN != 0);
+        assert_eq!(event.source.revents(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_empty() -> io::Result<()> {
+        let mut sources = Sources::<&str>::new();
+        let mut events = Events::new();
+        let mut other = Events::new();
+
+        // Would block forever if the empty set was polled.
+        sources.wait(&mut events)?;
+        assert!(events.is_empty());
+
+        sources.wait_timeout(&mut events, Duration::from_secs(60))?;
+        assert!(events.is_empty());
+
+        wait_any(&mut [(&mut sources, &mut other)], None)?;
+        assert!(other.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_until() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let past = time::Instant::now() - time::Duration::from_millis(10);
+        let err = sources.wait_until(&mut events, past).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let start = time::Instant::now();
+        let future = start + time::Duration::from_millis(10);
+        let err = sources.wait_until(&mut events, future).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= time::Duration::from_millis(10));
+
+        writer.write_all(&[1])?;
+        sources.wait_until(&mut events, past)?;
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_duplicate() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        let slot = sources.register_slot("reader", &reader0, interest::READ);
+        sources.register("writer", &writer0, interest::WRITE);
+        sources.register("reader", &reader1, interest::READ);
+
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources.get(&"reader").unwrap().fd(), reader1.as_raw_fd());
+        assert!(sources.get_by_slot(slot).is_none());
+        assert_eq!(
+            sources.keys().copied().collect::<Vec<_>>(),
+            vec!["reader", "writer"]
+        );
+
+        assert!(!sources.rekey(&"writer", "reader"));
+        assert!(sources.rekey(&"writer", "writer"));
+
+        assert!(sources.unregister(&"reader").is_some());
+        assert!(sources.get(&"reader").is_none());
+        assert_eq!(sources.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_keys() -> io::Result<()> {
+        use std::collections::HashSet;
+
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("writer0", &writer0, interest::WRITE);
+        sources.register("reader1", &reader1, interest::READ);
+        Waker::new(&mut sources, "waker")?;
+
+        let keys = sources.keys().copied().collect::<HashSet<_>>();
+        let expected = ["reader0", "writer0", "reader1", "waker"]
+            .iter()
+            .copied()
+            .collect::<HashSet<_>>();
+        assert_eq!(keys, expected);
+        assert_eq!(sources.iter().count(), sources.len());
+
+        for (key, source) in &sources {
+            assert_eq!(sources.get(key).map(Source::fd), Some(source.fd()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_many() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (writer1, reader1) = UnixStream::pair()?;
+
+        let mut sources = vec![
+            ("reader0", reader0.as_raw_fd(), interest::READ),
+            ("writer0", writer0.as_raw_fd(), interest::WRITE),
+        ]
+        .into_iter()
+        .collect::<Sources<_>>();
+
+        assert_eq!(sources.len(), 2);
+        assert!(sources.capacity() >= 2);
+
+        sources.extend(vec![("reader1", reader1.as_raw_fd(), interest::READ)]);
+        sources.register_many(vec![("writer1", writer1.as_raw_fd(), interest::WRITE)]);
+
+        assert_eq!(sources.len(), 4);
+        assert_eq!(sources.get(&"reader1").unwrap().fd(), reader1.as_raw_fd());
+        assert_eq!(sources.get(&"writer1").unwrap().events(), interest::WRITE);
+
+        let mut events = Events::new();
+        sources.wait_timeout(&mut events, time::Duration::from_secs(1))?;
+
+        let mut keys = events.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["writer0", "writer1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_unset_bits() -> io::Result<()> {
+        let (stream, _peer) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("stream", &stream, interest::READ);
+
+        assert!(sources.set(&"stream", interest::WRITE));
+        assert_eq!(sources.get(&"stream").unwrap().events(), interest::ALL);
+
+        assert!(sources.unset(&"stream", interest::WRITE));
+        assert_eq!(sources.get(&"stream").unwrap().events(), interest::READ);
+
+        assert!(!sources.set(&"other", interest::WRITE));
+        assert!(!sources.unset(&"other", interest::WRITE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_set_interest() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (writer1, _reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("writer1", &writer1, interest::WRITE);
+        writer0.write_all(&[1])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        sources.for_each_event(&events, |key, mut event| {
+            if key == &"writer1" {
+                assert!(event.unset_interest(interest::WRITE));
+            } else {
+                assert!(event.set_interest(interest::WRITE));
+            }
+        });
+        // Changes are applied to the registered sources right away.
+        assert_eq!(sources.get(&"writer1").unwrap().events(), interest::NONE);
+        assert_eq!(sources.get(&"reader0").unwrap().events(), interest::ALL);
+
+        // Changes made directly in the meantime aren't overridden.
+        sources.set(&"writer1", interest::WRITE);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(sources.get(&"writer1").unwrap().events(), interest::WRITE);
+
+        // Events of unregistered sources can't change anything.
+        sources.unregister(&"writer1");
+        sources.for_each_event(&events, |key, mut event| {
+            assert_eq!(event.unset_interest(interest::ALL), key == &"reader0");
+        });
+        assert_eq!(sources.get(&"reader0").unwrap().events(), interest::NONE);
+
+        // Disabled sources get their interests once enabled.
+        sources.register("writer1", &writer1, interest::WRITE);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        sources.disable(&"writer1");
+        sources.for_each_event(&events, |_, mut event| {
+            assert!(event.set_interest(interest::READ));
+        });
+        assert_eq!(sources.get(&"writer1").unwrap().events(), interest::NONE);
+        sources.enable(&"writer1");
+        assert_eq!(sources.get(&"writer1").unwrap().events(), interest::ALL);
+
+        // Events yielded by `Events` don't change their source.
+        for (_, mut event) in events.iter() {
+            assert!(!event.set_interest(interest::WRITE));
+        }
+        let source = Source::new(reader0.as_raw_fd(), interest::READ);
+        assert!(!Event::from(&source).set_interest(interest::WRITE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Events<&str>>();
+        assert_send_sync::<Event<'_>>();
+        assert_send_sync::<Iter<'_, &str>>();
+    }
+
+    #[test]
+    fn test_modify() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        assert_eq!(
+            sources.modify(&"reader", interest::WRITE),
+            Some(interest::READ)
+        );
+        assert_eq!(
+            sources.modify(&"reader", interest::WRITE),
+            Some(interest::WRITE)
+        );
+        assert_eq!(sources.get(&"reader").unwrap().events(), interest::WRITE);
+        assert_eq!(sources.modify(&"writer", interest::WRITE), None);
+
+        sources.disable(&"reader");
+        assert_eq!(
+            sources.modify(&"reader", interest::READ),
+            Some(interest::WRITE)
+        );
+        assert_eq!(sources.get(&"reader").unwrap().events(), interest::NONE);
+
+        sources.enable(&"reader");
+        assert_eq!(sources.get(&"reader").unwrap().events(), interest::READ);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_oneshot() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register_oneshot("oneshot", &reader, interest::READ);
+        sources.register("reader", &reader, interest::READ);
+        sources.register_oneshot("writer", &writer, interest::WRITE);
+        sources.register("idle", &writer, interest::NONE);
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["oneshot", "reader", "writer"]);
+        assert!(!sources.contains(&"oneshot"));
+        assert!(!sources.contains(&"writer"));
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources.get(&"reader").unwrap().fd(), reader.as_raw_fd());
+        assert_eq!(sources.get(&"idle").unwrap().events(), interest::NONE);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_owned() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        // The reader is only kept open by the set.
+        sources.register_owned("reader", OwnedFd::from(reader), interest::READ);
+        writer.write_all(&[1])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.readable && !event.invalid);
+
+        let fd = event.as_fd().unwrap();
+        let mut stream = std::fs::File::from(fd.try_clone_to_owned()?);
+        let mut buf = [0; 1];
+        stream.read_exact(&mut buf)?;
+        assert_eq!(buf, [1]);
+
+        // Unregistering closes the reader, which the writer sees as a hangup.
+        sources.unregister(&"reader");
+        drop(stream);
+        assert!(sources.is_empty());
+
+        sources.register("writer", &writer, interest::WRITE);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.hangup);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_owned_shared_fd() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let fd = reader.as_raw_fd();
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register_owned("owned", OwnedFd::from(reader), interest::READ);
+        sources.register("borrowed", &fd, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+
+        // Unregistering a source that shares the owned file descriptor doesn't
+        // close it.
+        sources.unregister(&"borrowed");
+        assert!(unsafe { libc::fcntl(fd, libc::F_GETFD) } >= 0);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (key, event) = events.iter().next().unwrap();
+        assert_eq!(key, &"writer");
+        assert!(!event.hangup);
+
+        // Replacing the owned source closes its file descriptor.
+        sources.register("owned", &writer, interest::NONE);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (_, event) = events.iter().find(|(k, _)| *k == &"writer").unwrap();
+        assert!(event.hangup);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_accessors() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        let source = sources.get(&"reader").unwrap();
+        assert_eq!(source.fd(), reader.as_raw_fd());
+        assert_eq!(source.events(), interest::READ);
+        assert_eq!(source.revents(), 0);
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert_eq!(event.source.revents(), libc::POLLIN);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unregister_returns_source() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+        let mut other = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.set(&"reader", interest::WRITE);
+
+        let source = sources.unregister(&"reader").unwrap();
+        assert_eq!(source.fd(), reader.as_raw_fd());
+        assert_eq!(source.events(), interest::READ | interest::WRITE);
+        assert!(sources.unregister(&"reader").is_none());
+
+        other.register("reader", &source, source.events());
+        assert_eq!(other.get(&"reader").unwrap().events(), source.events());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_iter() -> io::Result<()> {
+        struct Loop<'a> {
+            ready: Iter<'a, &'static str>,
+        }
+
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let mut keys = vec![];
+        for (key, event) in &events {
+            assert!(event.readable || event.writable);
+            keys.push(*key);
+        }
+        assert_eq!(keys, vec!["reader", "writer"]);
+
+        let l = Loop {
+            ready: events.iter(),
+        };
+        assert_eq!(l.ready.size_hint(), (0, Some(2)));
+        assert_eq!(l.ready.count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+        let waker = Waker::new(&mut sources, "waker")?;
+        let capacity = sources.capacity();
+
+        sources.clear();
+        assert_eq!(sources.len(), 0);
+        assert_eq!(sources.capacity(), capacity);
+        assert!(!sources.contains(&"reader"));
+        assert!(wakers(&sources).is_empty());
+
+        waker.wake()?;
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.is_empty());
+
+        sources.register("reader", &reader, interest::READ);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_contains() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        assert!(sources.contains(&"reader"));
+        assert!(!sources.contains(&"writer"));
+        assert_eq!(sources.get(&"reader").unwrap().fd, reader.as_raw_fd());
+        assert!(sources.get(&"writer").is_none());
+
+        sources.unregister(&"reader");
+        assert!(!sources.contains(&"reader"));
+        assert!(sources.get(&"reader").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_len() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        assert!(sources.is_empty());
+        assert_eq!(sources.len(), 0);
+
+        sources.register("reader", &reader, interest::READ);
+        let _waker = Waker::new(&mut sources, "waker")?;
+        assert!(!sources.is_empty());
+        assert_eq!(sources.len(), 2);
+
+        let sources = Sources::<&str>::with_capacity(16);
+        assert!(sources.capacity() >= 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_sources() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::with_capacity(10_000);
+
+        for key in 0..10_000 {
+            sources.register(key, &reader, key as Interest);
+        }
+        for key in (0..10_000).step_by(3) {
+            sources.unregister(&key);
+        }
+        assert_eq!(sources.len(), 10_000 - 3334);
+
+        for key in 0..10_000 {
+            match sources.find(&key) {
+                Some(ix) => {
+                    assert!(key % 3 != 0);
+                    assert_eq!(sources.index[ix], key);
+                    assert_eq!(sources.list[ix].events, key as Interest);
+                }
+                None => assert!(key % 3 == 0),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::with_capacity(64);
+
+        for key in 0..64 {
+            sources.register(key, &reader, interest::READ);
+        }
+        let waker = Waker::new(&mut sources, 64)?;
+        let slot = sources.slot(&63).unwrap();
+
+        for key in 8..56 {
+            sources.unregister(&key);
+        }
+        sources.compact();
+        assert!(sources.list.capacity() < 64);
+
+        sources.reserve(64);
+        assert!(sources.capacity() >= sources.len() + 64);
+        assert!(sources.state.capacity() >= sources.len() + 64);
+        sources.compact();
+        assert!(sources.capacity() < 64);
+
+        sources.compact_sorted();
+        let keys = (0..8).chain(56..65).collect::<Vec<_>>();
+        assert_eq!(sources.index, keys);
+        // The slot follows the source, which was moved twice.
+        assert_eq!(sources.slot(&63), Some(slot));
+        assert_eq!(sources.get_by_slot(slot), sources.get(&63));
+
+        for (ix, key) in keys.iter().enumerate() {
+            assert_eq!(sources.find(key), Some(ix));
+        }
+        assert_eq!(sources.get_mut(&64).unwrap().fd, waker.reader_fd());
+        assert_eq!(wakers(&sources), vec![waker.reader_fd()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let mut other = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        other.register("reader1", &reader1, interest::READ);
+        let waker = Waker::new(&mut other, "waker")?;
+
+        let err = sources.merge(&mut sources.clone()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(sources.len(), 1);
+
+        let mut dup = Sources::new();
+        dup.register("reader0", &reader1, interest::READ);
+        dup.register("reader2", &reader1, interest::READ);
+        assert!(sources.merge(&mut dup).is_err());
+        assert_eq!(dup.len(), 2);
+
+        sources.merge(&mut other)?;
+        assert_eq!(sources.index, vec!["reader0", "reader1", "waker"]);
+        assert!(other.is_empty());
+        assert!(other.get(&"reader1").is_none());
+
+        writer0.write_all(&[0])?;
+        waker.wake()?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader0", "waker"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_woken() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        writer.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(!events.woken());
+
+        waker.wake()?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.woken());
+
+        sources.unregister(&"waker");
+        waker.wake()?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(!events.woken());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_has_io() -> io::Result<()> {
+        let (mut writer, mut reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        waker.wake()?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.woken());
+        assert!(!events.has_io());
+
+        writer.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.woken());
+        assert!(events.has_io());
+
+        reader.read_exact(&mut [0])?;
+        sources.unregister(&"waker");
+        writer.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.has_io());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_accept() -> io::Result<()> {
+        use std::net;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        listener.set_nonblocking(true)?;
+        sources.register("listener", &listener, interest::READ);
+
+        let _conn0 = net::TcpStream::connect(addr)?;
+        let _conn1 = net::TcpStream::connect(addr)?;
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        let (_, event) = events.iter().next().unwrap();
+
+        let mut accepted = vec![];
+        while let Some(stream) = event.accept::<net::TcpStream>()? {
+            accepted.push(stream);
+        }
+        assert_eq!(accepted.len(), 2);
+
+        let mut buf = [0u8; 1];
+        assert_eq!(
+            (&accepted[0]).read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_const() {
+        const SOURCES: [Source; 2] = [
+            Source::new(0, interest::READ),
+            Source::new(1, interest::WRITE),
+        ];
+
+        assert_eq!(SOURCES[0].fd, 0);
+        assert_eq!(SOURCES[1].events, interest::WRITE);
+        assert_eq!(SOURCES[1].revents, 0);
+    }
+
+    #[test]
+    fn test_reserve_wakers() -> io::Result<()> {
+        let mut sources = Sources::new();
+
+        sources.reserve_wakers(16);
+        assert!(sources.capacity() >= 16);
+
+        let _waker = Waker::new(&mut sources, "waker")?;
+        assert_eq!(wakers(&sources).len(), 1);
+        assert!(sources.capacity() >= 16);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_set() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::NONE);
+
+        assert_eq!(sources.try_set(&"reader", interest::ALL), Ok(()));
+        assert_eq!(sources.try_unset(&"reader", interest::WRITE), Ok(()));
+        assert_eq!(sources.list[0].events, interest::READ);
+
+        assert_eq!(
+            sources.try_set(&"writer", interest::READ),
+            Err(NotRegistered)
+        );
+        assert_eq!(
+            sources.try_unset(&"writer", interest::READ),
+            Err(NotRegistered)
+        );
+
+        let err: io::Error = sources
+            .try_set(&"writer", interest::READ)
+            .unwrap_err()
+            .into();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_filtered() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+        let (writer2, _reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("writer2", &writer2, interest::WRITE);
+        sources.register("reader1", &reader1, interest::READ);
+
+        writer0.write_all(&[0])?;
+        writer1.write_all(&[0])?;
+
+        let reading = |_: &&str, s: &Source| s.events & interest::READ != 0;
+        let writing = |_: &&str, s: &Source| s.events & interest::WRITE != 0;
+
+        sources.wait_filtered(&mut events, Duration::from_millis(1), reading)?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader0", "reader1"]);
+
+        sources.wait_filtered(&mut events, Duration::from_millis(1), writing)?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["writer2"]);
+
+        // Nothing matches, so nothing is polled, even with an infinite timeout.
+        sources.wait_filtered(&mut events, None, |k, _| k == &"reader2")?;
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_should_read() {
+        let source = Source::new(0, interest::READ);
+
+        assert!(Event::new(libc::POLLIN, &source).should_read());
+        assert!(Event::new(libc::POLLHUP, &source).should_read());
+        assert!(Event::new(libc::POLLIN | libc::POLLHUP, &source).should_read());
+        assert!(!Event::new(libc::POLLERR, &source).should_read());
+        assert!(!Event::new(libc::POLLNVAL, &source).should_read());
+        assert!(!Event::new(libc::POLLOUT, &source).should_read());
+    }
+
+    #[test]
+    fn test_as_pollfd_slice() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("writer", &writer, interest::WRITE);
+
+        let fds = sources.as_pollfd_slice();
+
+        assert_eq!(fds.len(), 2);
+        assert_eq!(fds[0].fd, reader.as_raw_fd());
+        assert_eq!(fds[0].events, interest::READ);
+        assert_eq!(fds[1].fd, writer.as_raw_fd());
+        assert_eq!(fds[1].events, interest::WRITE);
+        assert_eq!(fds[1].revents, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_layout() {
+        fn offsets(base: usize, fields: [usize; 3]) -> [usize; 3] {
+            [fields[0] - base, fields[1] - base, fields[2] - base]
+        }
+        let source = Source::new(0, interest::NONE);
+        let pollfd = libc::pollfd {
+            fd: 0,
+            events: 0,
+            revents: 0,
+        };
+
+        assert_eq!(
+            offsets(
+                &source as *const _ as usize,
+                [
+                    &source.fd as *const _ as usize,
+                    &source.events as *const _ as usize,
+                    &source.revents as *const _ as usize,
+                ]
+            ),
+            offsets(
+                &pollfd as *const _ as usize,
+                [
+                    &pollfd.fd as *const _ as usize,
+                    &pollfd.events as *const _ as usize,
+                    &pollfd.revents as *const _ as usize,
+                ]
+            ),
+        );
+        assert_eq!(
+            std::mem::size_of::<Source>(),
+            std::mem::size_of::<libc::pollfd>()
+        );
+    }
+
+    #[test]
+    fn test_unregister_while_iterating() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+
+        writer0.write_all(&[0])?;
+        writer1.write_all(&[0])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let mut handled = vec![];
+        for (key, event) in events.iter() {
+            assert!(event.readable);
+
+            // A handler for the first event unregisters both sources.
+            if sources.get_mut(key).is_some() {
+                sources.unregister(&"reader0");
+                sources.unregister(&"reader1");
+                handled.push(*key);
+            }
+        }
+        assert_eq!(handled, vec!["reader0"]);
+        assert!(sources.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_edge() -> io::Result<()> {
+        let (mut writer0, mut reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        reader0.set_nonblocking(true)?;
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+
+        assert!(sources.set_edge(&"reader0", true));
+        assert!(!sources.set_edge(&"reader2", true));
+
+        writer0.write_all(&[1])?;
+        writer1.write_all(&[1])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader0", "reader1"]);
+
+        // More data is pending, but the edge-triggered source doesn't fire again.
+        writer0.write_all(&[2])?;
+
+        for _ in 0..3 {
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["reader1"]);
+            assert_eq!(events.len(), 1);
+        }
+
+        // Once drained, the source fires again when new data arrives.
+        let mut buf = [0; 2];
+        assert_eq!(reader0.read(&mut buf)?, 2);
+        assert_eq!(
+            reader0.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        sources.unregister(&"reader1");
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer0.write_all(&[3])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader0"]);
+
+        // The source isn't drained, so waiting returns at once, without events.
+        sources.wait(&mut events)?;
+        assert!(events.is_empty());
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert!(events.is_empty());
+
+        let mut other = Sources::new();
+        let mut other_events = Events::new();
+        wait_any(
+            &mut [(&mut sources, &mut events), (&mut other, &mut other_events)],
+            None,
+        )?;
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_hasher() -> io::Result<()> {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::BuildHasherDefault;
+
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::with_hasher(BuildHasherDefault::<DefaultHasher>::default());
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.unregister(&"reader0");
+        sources.register("reader0", &reader0, interest::READ);
+
+        writer0.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let (key, _) = events.iter().next().unwrap();
+        assert_eq!(key, &"reader0");
+        assert_eq!(sources.find(&"reader1"), Some(0));
+        assert_eq!(sources.find(&"reader0"), Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_fds() -> io::Result<()> {
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        assert_eq!(sources.list[0].fd, waker.reader_fd());
+        assert_eq!(wakers(&sources), vec![waker.reader_fd()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fair() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+        let (mut writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        for writer in &mut [&mut writer0, &mut writer1, &mut writer2] {
+            writer.write_all(&[0])?;
+        }
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (first, _) = events.iter().next().unwrap();
+        assert_eq!(first, &"reader0");
+
+        sources.set_fair(true);
+
+        let mut firsts = vec![];
+        for _ in 0..4 {
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+            let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            assert_eq!(keys.len(), 3);
+
+            firsts.push(keys[0]);
+        }
+        assert_eq!(firsts, vec!["reader0", "reader1", "reader2", "reader0"]);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.drain().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader1", "reader2", "reader0"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unset_while_iterating() -> io::Result<()> {
+        let (writer0, _reader0) = UnixStream::pair()?;
+        let (writer1, _reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("writer0", &writer0, interest::ALL);
+        sources.register("writer1", &writer1, interest::ALL);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        for (key, event) in events.iter() {
+            assert!(event.writable);
+
+            // Done writing.
+            assert!(sources.unset(key, interest::WRITE));
+        }
+        assert_eq!(sources.get_mut(&"writer0").unwrap().events, interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_error() {
+        let err = poll_error(io::Error::from_raw_os_error(libc::EINVAL), usize::MAX);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().starts_with("too many sources to poll"));
+
+        let err = poll_error(io::Error::from_raw_os_error(libc::EINVAL), 1);
+        assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+        let err = poll_error(io::Error::from_raw_os_error(libc::EFAULT), 1);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let err = poll_error(io::Error::from_raw_os_error(libc::EINTR), 1);
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+    }
+
+    #[test]
+    fn test_set_nonblocking() -> io::Result<()> {
+        let (_writer, mut reader) = UnixStream::pair()?;
+        let mut buf = [0; 1];
+
+        assert!(!set_nonblocking(&reader, true)?);
+        assert!(set_nonblocking(&reader, true)?);
+        assert_eq!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert!(set_nonblocking(&reader, false)?);
+        assert!(!set_nonblocking(&reader, false)?);
+
+        let mut sources = Sources::new();
+
+        sources.register_nonblocking("reader", &reader, interest::READ)?;
+        assert!(set_nonblocking(&reader, true)?);
+        sources.unregister(&"reader");
+
+        let nonblocking = sources.register_stdin("stdin")?;
+        assert!(set_nonblocking(&io::stdin(), nonblocking)?);
+        sources.register_stdout("stdout");
+        sources.register_stderr("stderr");
+
+        assert_eq!(sources.as_pollfd_slice()[0].fd, 0);
+        assert_eq!(sources.as_pollfd_slice()[1].events, interest::WRITE);
+        assert_eq!(sources.as_pollfd_slice()[2].fd, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dispatch() -> io::Result<()> {
+        use std::net;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let mut peers = vec![];
+
+        listener.set_nonblocking(true)?;
+        sources.register("listener", &listener, interest::READ);
+
+        let _conn = net::TcpStream::connect(listener.local_addr()?)?;
+
+        sources.dispatch(
+            &mut events,
+            Duration::from_secs(1),
+            |sources, key, flags| {
+                assert_eq!(key, "listener");
+                assert_eq!(flags.fd, listener.as_raw_fd());
+                assert!(flags.readable);
+
+                let (peer, _) = listener.accept().unwrap();
+                sources.register("peer", &peer, interest::WRITE);
+                peers.push(peer);
+            },
+        )?;
+        assert_eq!(sources.len(), 2);
+
+        let mut keys = vec![];
+        sources.dispatch(&mut events, Duration::from_secs(1), |sources, key, _| {
+            sources.unregister(&key);
+            keys.push(key);
+        })?;
+        assert_eq!(keys, vec!["peer"]);
+        assert_eq!(sources.len(), 1);
+
+        let waker = Waker::new(&mut sources, "waker")?;
+        waker.wake()?;
+
+        let mut keys = vec![];
+        sources.dispatch(&mut events, Duration::from_secs(1), |_, key, _| {
+            keys.push(key);
+        })?;
+        assert_eq!(keys, vec!["waker"]);
+
+        let err = sources
+            .dispatch(&mut events, Duration::from_millis(1), |_, _, _| {})
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut, "the waker was reset");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+        let (_writer2, reader2) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        sources.register("reader1", &reader1, interest::READ);
+        sources.register("reader2", &reader2, interest::READ);
+
+        writer0.write_all(&[0])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let idle = events.idle().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(idle, vec!["reader1", "reader2"]);
+
+        sources.wait_filtered(&mut events, Duration::from_millis(1), |k, _| {
+            k != &"reader2"
+        })?;
+        let idle = events.idle().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(idle, vec!["reader1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_flooded() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources.register("reader", &reader, interest::READ);
+
+        for _ in 0..4096 {
+            waker.wake()?;
+        }
+        writer.write_all(&[0])?;
+
+        for _ in 0..3 {
+            sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+            let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            assert_eq!(keys, vec!["waker", "reader"]);
+            waker.wake()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_poll_negative_fd() -> io::Result<()> {
+        let (writer, _reader) = UnixStream::pair()?;
+        let mut list = [
+            Source::new(-1, interest::WRITE),
+            Source::new(writer.as_raw_fd(), interest::WRITE),
+        ];
+        list[0].revents = interest::WRITE;
+
+        // Negative file descriptors are skipped, and their events are cleared.
+        assert_eq!(poll(&mut list, &mut Scratch::new(), 0)?, 1);
+        assert_eq!(list[0].revents, 0);
+        assert_ne!(list[1].revents & libc::POLLOUT, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_dropped() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        waker.wake()?;
+        drop(waker);
+
+        // The file descriptor of the waker may be reused by another test running
+        // concurrently, in which case it isn't reported as invalid.
+        match sources.wait_timeout(&mut events, Duration::from_millis(1)) {
+            Ok(()) => {
+                for (_, event) in events.iter() {
+                    if event.invalid {
+                        assert_eq!(event.waker_count(), None);
+                    }
+                }
+            }
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_full() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        for _ in 0..1_000_000 {
+            waker.wake()?;
+        }
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert_eq!(event.waker_count(), None);
+
+        let (_, event) = events.drain().next().unwrap();
+        let count = event.waker_count().unwrap();
+        // An `eventfd` counts all wake-ups, while a socket buffer fills up.
+        #[cfg(target_os = "linux")]
+        assert_eq!(count, 1_000_000);
+        #[cfg(not(target_os = "linux"))]
+        assert!(count > 0 && count < 1_000_000);
+        assert_eq!(waker.snooze()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_snooze() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        for _ in 0..5 {
+            waker.wake()?;
+        }
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.len(), 1);
+
+        // All pending wake-ups are drained at once.
+        assert_eq!(waker.snooze()?, 5);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_try_clone() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        let handles = (0..3)
+            .map(|_| {
+                let waker = waker.try_clone()?;
+                assert_eq!(waker.reader_fd(), sources.get(&"waker").unwrap().fd());
+
+                Ok(thread::spawn(move || waker.wake()))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (_, event) = events.drain().next().unwrap();
+        assert_eq!(event.waker_count(), Some(3));
+
+        // The reading end is kept open by the original handle.
+        waker.wake()?;
+        assert_eq!(waker.snooze()?, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_after_unregister() -> io::Result<()> {
+        let (_writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("other", &reader, interest::READ);
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        // The waker is moved to the position of the unregistered source.
+        sources.unregister(&"reader");
+        assert_eq!(sources.find(&"waker"), Some(0));
+        assert_eq!(sources.find(&"other"), Some(1));
+
+        sources.unregister(&"other");
+        assert_eq!(wakers(&sources), vec![waker.reader_fd()]);
+
+        waker.wake()?;
+        waker.wake()?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        assert!(events.woken());
+
+        let ready = events.drain_ready()?;
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].0, "waker");
+        assert_eq!(ready[0].1.waker_count, Some(2));
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_count() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources.register("reader", &reader, interest::READ);
+
+        for _ in 0..3 {
+            waker.wake()?;
+        }
+        writer.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        // Iterating doesn't drain the waker.
+        for (_, event) in events.iter() {
+            assert_eq!(event.waker_count(), None);
+        }
+        for (key, event) in events.drain() {
+            match *key {
+                "waker" => assert_eq!(event.waker_count(), Some(3)),
+                "reader" => assert_eq!(event.waker_count(), None),
+                _ => unreachable!(),
+            }
+        }
+        assert_eq!(waker.snooze()?, 0);
+
+        // Dispatching drains the waker, and reports its count.
+        waker.wake()?;
+        sources.dispatch(
+            &mut events,
+            Duration::from_millis(1),
+            |_, key, flags| match key {
+                "waker" => assert_eq!(flags.waker_count, Some(1)),
+                "reader" => assert_eq!(flags.waker_count, None),
+                _ => unreachable!(),
+            },
+        )?;
+        assert_eq!(waker.snooze()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker_reused_fd() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        // The file descriptor of the waker is likely reused by the socket.
+        sources.unregister(&"waker");
+        drop(waker);
+
+        let (mut writer, mut reader) = UnixStream::pair()?;
+        sources.register("reader", &reader, interest::READ);
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        assert!(!events.woken());
+        assert!(events.has_io());
+
+        let (_, event) = events.iter().next().unwrap();
+        assert_eq!(event.waker_count(), None);
+
+        // The socket isn't snoozed like a waker.
+        events.drain_ready()?;
+
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf)?, 1);
+        assert_eq!(buf[0], 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_timeout_conversions() -> io::Result<()> {
+        assert_eq!(Timeout::from(()).as_millis(), -1);
+        assert_eq!(Timeout::from(None).as_millis(), -1);
+        assert_eq!(Timeout::Immediate.as_millis(), 0);
+        assert_eq!(Timeout::from(Duration::from_micros(1500)).as_millis(), 2);
+        assert_eq!(Timeout::from(Duration::from_micros(1)).as_millis(), 1);
+        assert_eq!(Timeout::from(Duration::from_millis(3)).as_millis(), 3);
+        assert_eq!(Timeout::from(Duration::from_secs(0)).as_millis(), 0);
+        assert_eq!(Timeout::from_millis(-1), Timeout::Infinite);
+        assert_eq!(Timeout::from_millis(0), Timeout::Immediate);
+        assert_eq!(
+            Timeout::from(Some(Duration::from_secs(u64::MAX))).as_millis(),
+            libc::c_int::MAX
+        );
+        // Huge timeouts don't wrap around to a negative, ie. infinite, timeout.
+        assert_eq!(
+            Timeout::from(Duration::from_secs(u64::MAX / 1000)).as_millis(),
+            libc::c_int::MAX
+        );
+        assert_eq!(
+            Timeout::from(Duration::from_millis(libc::c_int::MAX as u64 + 1)).as_millis(),
+            libc::c_int::MAX
+        );
+
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, Timeout::Immediate)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, None)?;
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_drain_ready() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(&[1])?;
+        waker.wake()?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let ready = events.drain_ready()?;
+
+        for (key, flags) in ready {
+            assert!(flags.readable);
+            sources.unregister(&key);
+        }
+        assert!(sources.is_empty());
+        assert_eq!(events.iter().count(), 0);
+        assert_eq!(waker.snooze()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reap_idle() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader0", &reader0, interest::READ);
+        assert_eq!(sources.last_active(&"reader0"), None);
+
+        sources.set_track_activity(true);
+        sources.register("reader1", &reader1, interest::READ);
+        let registered = sources.last_active(&"reader1").unwrap();
+
+        thread::sleep(Duration::from_millis(20));
+        writer0.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+
+        assert!(sources.last_active(&"reader0").unwrap() > registered);
+        assert_eq!(sources.last_active(&"reader1"), Some(registered));
+        assert_eq!(
+            sources.reap_idle(Duration::from_millis(10)),
+            vec!["reader1"]
+        );
+        assert_eq!(sources.len(), 1);
+        assert!(sources.reap_idle(Duration::from_secs(10)).is_empty());
+
+        sources.set_track_activity(false);
+        assert!(sources.reap_idle(Duration::from_secs(0)).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_child_process() -> io::Result<()> {
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("cat")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = child.stdout.take().unwrap();
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let mut output = Vec::new();
+        let mut buf = [0; 16];
+
+        sources.register_nonblocking("stdout", &stdout, interest::READ)?;
+        assert_eq!(
+            stdout.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        stdin.write_all(b"hello")?;
+        drop(stdin);
+
+        while !sources.is_empty() {
+            sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+            for (key, event) in events.iter() {
+                assert!(event.should_read());
+
+                loop {
+                    match stdout.read(&mut buf) {
+                        Ok(0) => {
+                            sources.unregister(key);
+                            break;
+                        }
+                        Ok(n) => output.extend_from_slice(&buf[..n]),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        assert_eq!(output, b"hello");
+        assert!(child.wait()?.success());
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+    fn test_wait_with_sigmask() -> io::Result<()> {
+        extern "C" fn handler(_: libc::c_int) {}
+
+        let (_writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            assert_eq!(
+                libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut()),
+                0
+            );
+        }
+        let mut blocked: libc::sigset_t = unsafe { std::mem::zeroed() };
+        let mut unblocked: libc::sigset_t = unsafe { std::mem::zeroed() };
+        let mut previous: libc::sigset_t = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            libc::sigemptyset(&mut blocked);
+            libc::sigaddset(&mut blocked, libc::SIGUSR2);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &blocked, &mut previous);
+            libc::pthread_sigmask(libc::SIG_BLOCK, std::ptr::null(), &mut unblocked);
+            libc::sigdelset(&mut unblocked, libc::SIGUSR2);
+
+            // Raise a signal while it is blocked, so that it is pending.
+            libc::pthread_kill(libc::pthread_self(), libc::SIGUSR2);
+        }
+
+        // The signal stays blocked during the wait, so it doesn't interrupt it.
+        let err = sources
+            .wait_with_sigmask(&mut events, Duration::from_millis(10), &blocked)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        // The signal is delivered during the wait, interrupting it.
+        let err = sources
+            .wait_with_sigmask(&mut events, Duration::from_secs(1), &unblocked)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+
+        unsafe {
+            libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "freebsd", target_os = "linux"))]
+    fn test_wait_precise() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let start = time::Instant::now();
+        let err = sources
+            .wait_precise(&mut events, Duration::from_micros(500))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_micros(500));
+
+        writer.write_all(&[1])?;
+        sources.wait_precise(&mut events, Duration::from_micros(500))?;
+        assert_eq!(events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_forever() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let handle = thread::spawn(move || -> io::Result<UnixStream> {
+            thread::sleep(Duration::from_millis(50));
+            (&writer).write_all(&[1])?;
+
+            Ok(writer)
+        });
+        let start = time::Instant::now();
+
+        sources.wait(&mut events)?;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(events.len(), 1);
+
+        let writer = handle.join().unwrap()?;
+        let mut buf = [0; 1];
+        (&reader).read_exact(&mut buf)?;
+
+        // Passing no timeout also blocks until an event is returned.
+        let handle = thread::spawn(move || -> io::Result<()> {
+            thread::sleep(Duration::from_millis(50));
+            (&writer).write_all(&[1])
+        });
+        sources.wait_timeout(&mut events, None)?;
+        assert_eq!(events.len(), 1);
+        handle.join().unwrap()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_interrupted() -> io::Result<()> {
+        extern "C" fn handler(_: libc::c_int) {}
+
+        /// Restores the previous signal handler when dropped, even if the test fails.
+        struct Restore(libc::sigaction);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                unsafe { libc::sigaction(libc::SIGUSR1, &self.0, std::ptr::null_mut()) };
+            }
+        }
+
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let _restore = unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            assert_eq!(libc::sigaction(libc::SIGUSR1, &action, &mut previous), 0);
+
+            Restore(previous)
+        };
+        let thread = unsafe { libc::pthread_self() } as usize;
+        let handle = thread::spawn(move || -> io::Result<UnixStream> {
+            thread::sleep(Duration::from_millis(20));
+            unsafe { libc::pthread_kill(thread as libc::pthread_t, libc::SIGUSR1) };
+            thread::sleep(Duration::from_millis(40));
+            writer.write_all(&[1])?;
+
+            Ok(writer)
+        });
+
+        // The signal interrupts `poll`, which is retried until the reader is ready.
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert_eq!(events.len(), 1);
+        let _writer = handle.join().unwrap()?;
+
+        let mut buf = [0; 1];
+        (&reader).read_exact(&mut buf)?;
+
+        // The wait is resumed after the signal, until the timeout expires.
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            unsafe { libc::pthread_kill(thread as libc::pthread_t, libc::SIGUSR1) };
+        });
+        let start = time::Instant::now();
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(60))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        handle.join().unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_into() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let mut out = Vec::with_capacity(4);
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources.register("reader", &reader, interest::READ);
+
+        for _ in 0..3 {
+            writer.write_all(&[1])?;
+            waker.wake()?;
+
+            let n = sources.wait_into(&mut events, Duration::from_millis(1), &mut out)?;
+            assert_eq!(n, 2);
+            assert_eq!(out[0].0, "waker");
+            assert_eq!(out[1].0, "reader");
+            assert_eq!(out.capacity(), 4);
+
+            let mut buf = [0; 1];
+            (&reader).read_exact(&mut buf)?;
+        }
+        // The waker was drained.
+        assert_eq!(waker.snooze()?, 0);
+
+        // The buffer is cleared when the wait times out.
+        writer.write_all(&[1])?;
+        sources.wait_into(&mut events, Duration::from_millis(1), &mut out)?;
+        assert_eq!(out.len(), 1);
+        (&reader).read_exact(&mut [0])?;
+
+        let err = sources
+            .wait_into(&mut events, Duration::from_millis(1), &mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(out.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_any() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut sources0 = Sources::new();
+        let mut sources1 = Sources::new();
+        let mut events0 = Events::new();
+        let mut events1 = Events::new();
+
+        sources0.register("reader0", &reader0, interest::READ);
+        sources1.register("reader1", &reader1, interest::READ);
+
+        let err = wait_any(
+            &mut [(&mut sources0, &mut events0), (&mut sources1, &mut events1)],
+            Duration::from_millis(1),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer1.write_all(&[1])?;
+        wait_any(
+            &mut [(&mut sources0, &mut events0), (&mut sources1, &mut events1)],
+            Duration::from_millis(1),
+        )?;
+        assert!(events0.is_empty());
+        assert_eq!(
+            events1.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec!["reader1"]
+        );
+
+        writer0.write_all(&[1])?;
+        wait_any(
+            &mut [(&mut sources0, &mut events0), (&mut sources1, &mut events1)],
+            Duration::from_millis(1),
+        )?;
+        assert_eq!(
+            events0.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec!["reader0"]
+        );
+        assert_eq!(events1.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flags() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        writer.write_all(&[0])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let flags = events.iter().map(|(_, e)| e.flags()).collect::<Vec<_>>();
+
+        // The events can be dropped.
+        drop(events);
+
+        assert_eq!(
+            flags,
+            vec![EventFlags {
+                fd: reader.as_raw_fd(),
+                writable: false,
+                readable: true,
+                priority: false,
+                read_closed: false,
+                hangup: false,
+                errored: false,
+                invalid: false,
+                waker_count: None,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_disabled() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register_disabled("reader", &reader, interest::READ);
+        writer.write_all(&[0])?;
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        assert!(sources.enable(&"reader"));
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.len(), 1);
+
+        // Enabling twice has no effect.
+        assert!(sources.enable(&"reader"));
+        assert_eq!(sources.list[0].events, interest::READ);
+
+        assert!(sources.disable(&"reader"));
+        assert!(sources.disable(&"reader"));
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        assert!(sources.enable(&"reader"));
+        assert_eq!(sources.list[0].events, interest::READ);
+
+        // Interests set while disabled are only waited for once enabled.
+        let slot = sources.slot(&"reader").unwrap();
+        assert!(sources.disable(&"reader"));
+        assert!(sources.set(&"reader", interest::WRITE));
+        assert!(sources.unset(&"reader", interest::READ));
+        assert!(sources.set_by_slot(slot, interest::PRI));
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        assert!(sources.enable(&"reader"));
+        assert_eq!(sources.list[0].events, interest::WRITE | interest::PRI);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.iter().next().unwrap().1.writable);
+
+        assert!(!sources.enable(&"writer"));
+        assert!(!sources.disable(&"writer"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default() -> io::Result<()> {
+        #[derive(Default)]
+        struct Reactor {
+            sources: Sources<&'static str>,
+            events: Events<&'static str>,
+        }
+        let mut reactor = Reactor::default();
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        assert!(reactor.sources.is_empty());
+        assert!(reactor.events.is_empty());
+
+        reactor.sources.register("reader", &reader, interest::READ);
+        writer.write_all(&[0])?;
+
+        let mut sources = std::mem::take(&mut reactor.sources);
+        assert!(reactor.sources.is_empty());
+
+        sources.wait_timeout(&mut reactor.events, Duration::from_millis(1))?;
+        assert_eq!(reactor.events.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(any(target_os = "android", target_os = "linux"))]
+    fn test_read_closed() -> io::Result<()> {
+        use std::net;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let sender = net::TcpStream::connect(listener.local_addr()?)?;
+        let (receiver, _) = listener.accept()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("receiver", &receiver, interest::RDHUP);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        sender.shutdown(net::Shutdown::Write)?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.read_closed);
+        assert!(event.flags().read_closed);
+        assert!(!event.hangup);
+
+        // The receiver can still write.
+        (&receiver).write_all(&[1])?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_priority() -> io::Result<()> {
+        use std::net;
+
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let sender = net::TcpStream::connect(listener.local_addr()?)?;
+        let (receiver, _) = listener.accept()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("receiver", &receiver, interest::PRI);
+
+        (&sender).write_all(&[1])?;
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        let n = unsafe {
+            libc::send(
+                sender.as_raw_fd(),
+                [2u8].as_ptr() as *const _,
+                1,
+                libc::MSG_OOB,
+            )
+        };
+        assert_eq!(n, 1);
+
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.priority);
+        assert!(event.flags().priority);
+        // For compatibility, urgent data also makes the source readable.
+        assert!(event.readable);
+        assert_eq!(event.recv_oob()?, Some(2));
+        assert_eq!(event.recv_oob()?, None);
+
+        let socket = net::UdpSocket::bind("127.0.0.1:0")?;
+        let source = Source::new(socket.as_raw_fd(), interest::PRI);
+        let event = Event::new(libc::POLLPRI, &source);
+        assert_eq!(
+            event.recv_oob().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_slot() -> io::Result<()> {
+        let (writer0, reader0) = UnixStream::pair()?;
+        let (_writer1, reader1) = UnixStream::pair()?;
+
+        let mut sources = Sources::new();
+
+        let slot0 = sources.register_slot("reader0", &reader0, interest::NONE);
+        let slot1 = sources.register_slot("reader1", &reader1, interest::NONE);
+
+        assert_eq!(sources.slot(&"reader0"), Some(slot0));
+        assert!(sources.set_by_slot(slot1, interest::READ));
+        assert_eq!(sources.get_by_slot(slot1).unwrap().events, interest::READ);
+        assert_eq!(sources.get_by_slot(slot0).unwrap().fd, reader0.as_raw_fd());
+
+        // Unregistering moves the last source into the freed position, and its
+        // slot follows it.
+        sources.unregister(&"reader0");
+        assert!(sources.get_by_slot(slot0).is_none());
+        assert!(!sources.set_by_slot(slot0, interest::READ));
+        assert_eq!(sources.get_by_slot(slot1).unwrap().fd, reader1.as_raw_fd());
+        assert_eq!(sources.slot(&"reader1"), Some(slot1));
+        assert!(sources.set_by_slot(slot1, interest::WRITE));
+        assert_eq!(sources.get_by_slot(slot1).unwrap().events, interest::ALL);
+
+        // A new source in the same position doesn't reuse the slot.
+        let slot2 = sources.register_slot("writer0", &writer0, interest::NONE);
+        sources.unregister(&"writer0");
+        sources.register("reader0", &reader0, interest::NONE);
+        assert!(sources.get_by_slot(slot2).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_only_hangup() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("writer", &writer, interest::WRITE);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert!(events.iter().all(|(_, e)| e.writable && !e.hangup));
+
+        drop(reader);
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (key, event) = events.iter().next().unwrap();
+
+        assert_eq!(*key, "writer");
+        assert!(event.hangup);
+        assert!(event.is_closed());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_event_states() {
+        let source = Source::new(0, interest::READ);
+
+        let event = Event::new(libc::POLLIN, &source);
+        assert!(event.has_data() && !event.is_closed() && !event.is_fatal());
+
+        let event = Event::new(libc::POLLIN | libc::POLLHUP, &source);
+        assert!(event.has_data() && !event.is_closed() && !event.is_fatal());
+
+        let event = Event::new(libc::POLLHUP, &source);
+        assert!(!event.has_data() && event.is_closed() && !event.is_fatal());
+
+        let event = Event::new(libc::POLLIN | libc::POLLERR, &source);
+        assert!(!event.has_data() && !event.is_closed() && event.is_fatal());
+
+        let event = Event::new(libc::POLLNVAL, &source);
+        assert!(!event.has_data() && !event.is_closed() && event.is_fatal());
+    }
+
+    #[test]
+    fn test_register_split() -> io::Result<()> {
+        let (mut writer, mut stream) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register_split("read", "write", &stream);
+        writer.write_all(&[0])?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let ready = events
+            .iter()
+            .map(|(k, e)| (*k, e.readable, e.writable))
+            .collect::<Vec<_>>();
+        assert_eq!(ready, vec![("read", true, false), ("write", false, true)]);
+
+        // Draining via the read key doesn't affect the write key.
+        stream.read_exact(&mut [0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["write"]);
+
+        // Unsetting the write interest doesn't affect the read key.
+        sources.unset(&"write", interest::WRITE);
+        writer.write_all(&[0])?;
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["read"]);
+        assert_eq!(events.list[1].fd, stream.as_raw_fd());
+
+        // Both keys share a single file descriptor to poll.
+        sources.set(&"write", interest::WRITE);
+        events.initialize(&sources);
+        sources.prepare(&mut events);
+        let polled = events.list.iter().filter(|s| s.fd >= 0).collect::<Vec<_>>();
+        assert_eq!(polled.len(), 1);
+        assert_eq!(polled[0].events, interest::ALL);
+
+        // Hangups are reported under both keys.
+        drop(writer);
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let hangups = events
+            .iter()
+            .map(|(k, e)| (*k, e.hangup))
+            .collect::<Vec<_>>();
+        assert_eq!(hangups, vec![("read", true), ("write", true)]);
+
+        // Once the read key is unregistered, the write key is polled on its own.
+        sources.unregister(&"read");
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["write"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_split_pairs() -> io::Result<()> {
+        let (_writer, stream) = UnixStream::pair()?;
+        let (_other_writer, other) = UnixStream::pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("first", &other, interest::READ);
+        sources.register_split("read0", "write0", &stream);
+        sources.register_split("read1", "write1", &stream);
+
+        // The last source is moved first, before its read half.
+        sources.unregister(&"first");
+        assert_eq!(sources.find(&"write1"), Some(0));
+
+        // Each write half is merged into its own read half.
+        events.initialize(&sources);
+        sources.prepare(&mut events);
+        let mut splits = events
+            .splits
+            .iter()
+            .map(|&(r, w)| (sources.index[r], sources.index[w]))
+            .collect::<Vec<_>>();
+        splits.sort();
+        assert_eq!(splits, vec![("read0", "write0"), ("read1", "write1")]);
+
+        // Halves still find each other once merged into another set.
+        let mut merged = Sources::new();
+        merged.register("first", &other, interest::READ);
+        merged.merge(&mut sources)?;
+
+        events.initialize(&merged);
+        merged.prepare(&mut events);
+        assert_eq!(events.splits.len(), 2);
+
+        // A half registered again is no longer merged.
+        merged.register("read0", &stream, interest::READ);
+        events.initialize(&merged);
+        merged.prepare(&mut events);
+        let splits = events
+            .splits
+            .iter()
+            .map(|&(r, w)| (merged.index[r], merged.index[w]))
+            .collect::<Vec<_>>();
+        assert_eq!(splits, vec![("read1", "write1")]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_limits() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        for i in 0..8 {
+            sources.register(i, &io::stdout(), interest::NONE);
+        }
+        sources.check_limits()?;
+        assert!(check_limits(usize::MAX).is_err());
+
+        sources.set_check_limits(true);
+        sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .ok();
+
+        sources.set_check_limits(false);
+        assert!(!sources.limits);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_waker() -> io::Result<()> {
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, "waker")?;
+
+        sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .ok();
+        assert!(events.iter().next().is_none());
+
+        // Fill the waker stream until it would block..
+        #[cfg(not(target_os = "linux"))]
+        loop {
+            match (&waker.writer).write(&[0; 4096]) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    break;
+                }
+                Err(e) => return Err(e),
+                _ => continue,
+            }
+        }
+        // Fill the waker counter until it would block..
+        #[cfg(target_os = "linux")]
+        {
+            (&*waker.reader).write_all(&(u64::MAX - 1).to_ne_bytes())?;
+            assert_eq!(
+                (&*waker.reader)
+                    .write(&1u64.to_ne_bytes())
+                    .unwrap_err()
+                    .kind(),
+                io::ErrorKind::WouldBlock
+            );
+        }
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (key, event) = events.iter().next().unwrap();
+
+        assert!(event.readable);
+        assert!(!event.writable && !event.hangup && !event.errored);
+        assert_eq!(key, &"waker");
+
+        waker.wake()?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        let (key, event) = events.iter().next().unwrap();
+
+        assert!(event.readable);
+        assert_eq!(key, &"waker");
+
+        // Try to wake multiple times.
+        waker.wake()?;
+        waker.wake()?;
+        waker.wake()?;
+
+        sources.wait_timeout(&mut events, Duration::from_millis(1))?;
+        assert_eq!(events.iter().count(), 1, "multiple wakes count as one");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! A single object bundling sources and their events.
+use std::hash::Hash;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+use crate::{Events, Interest, Source, Sources, Timeout, Waker};
+
+/// Sources to poll, along with the events buffer they are polled into. This
+/// is a thin wrapper around `Sources` and `Events`, for event loops that would
+/// rather hold a single object.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::os::unix::net::UnixStream;
+/// use std::time::Duration;
+///
+/// use popol::{interest, Poll};
+///
+/// let (mut writer, reader) = UnixStream::pair()?;
+/// let mut poll = Poll::new();
+///
+/// poll.register("reader", &reader, interest::READ);
+/// writer.write_all(b"hello")?;
+///
+/// for (key, event) in poll.poll(Duration::from_secs(1))?.iter() {
+///     assert_eq!(key, &"reader");
+///     assert!(event.readable);
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Poll<K> {
+    /// Sources to poll.
+    sources: Sources<K>,
+    /// Events buffer.
+    events: Events<K>,
+}
+
+impl<K: Eq + Hash + Clone> Default for Poll<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone> Poll<K> {
+    /// Create a new poller, with no sources.
+    pub fn new() -> Self {
+        Self {
+            sources: Sources::new(),
+            events: Events::new(),
+        }
+    }
+
+    /// Create a new poller, with the given capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            sources: Sources::with_capacity(cap),
+            events: Events::with_capacity(cap),
+        }
+    }
+
+    /// Register a new source. See `Sources::register`.
+    pub fn register(&mut self, key: K, fd: &impl AsRawFd, events: Interest) {
+        self.sources.register(key, fd, events);
+    }
+
+    /// Unregister a source. See `Sources::unregister`.
+    pub fn unregister(&mut self, key: &K) -> Option<Source> {
+        self.sources.unregister(key)
+    }
+
+    /// Set event interests on a source, in addition to the ones already set.
+    /// See `Sources::set`.
+    pub fn set(&mut self, key: &K, events: Interest) -> bool {
+        self.sources.set(key, events)
+    }
+
+    /// Replace the event interests of a source, and return the previous ones.
+    /// See `Sources::modify`.
+    pub fn modify(&mut self, key: &K, events: Interest) -> Option<Interest> {
+        self.sources.modify(key, events)
+    }
+
+    /// Unset event interests on a source. See `Sources::unset`.
+    pub fn unset(&mut self, key: &K, events: Interest) -> bool {
+        self.sources.unset(key, events)
+    }
+
+    /// Create a waker registered under the given key. See `Waker::new`.
+    pub fn waker(&mut self, key: K) -> io::Result<Waker> {
+        Waker::new(&mut self.sources, key)
+    }
+
+    /// Get the underlying sources, eg. to use methods not exposed by the poller.
+    pub fn sources(&mut self) -> &mut Sources<K> {
+        &mut self.sources
+    }
+
+    /// Wait for readiness events, and return them. See `Sources::wait_timeout`.
+    pub fn poll(&mut self, timeout: impl Into<Timeout>) -> io::Result<&mut Events<K>> {
+        self.sources.wait_timeout(&mut self.events, timeout)?;
+
+        Ok(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::prelude::*;
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    use crate::interest;
+
+    #[test]
+    fn test_poll() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut poll = Poll::new();
+
+        poll.register("reader", &reader, interest::READ);
+        poll.register("writer", &writer, interest::NONE);
+        let waker = poll.waker("waker")?;
+
+        let err = poll.poll(Duration::from_millis(1)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer.write_all(&[1])?;
+        waker.wake()?;
+
+        let ready = poll
+            .poll(Duration::from_millis(1))?
+            .drain_ready()?
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect::<Vec<_>>();
+        assert_eq!(ready, vec!["reader", "waker"]);
+
+        assert!(poll.unset(&"reader", interest::READ));
+        assert!(poll.set(&"writer", interest::WRITE));
+        assert_eq!(
+            poll.modify(&"writer", interest::WRITE),
+            Some(interest::WRITE)
+        );
+        assert!(poll.unregister(&"waker").is_some());
+        assert_eq!(poll.sources().len(), 2);
+
+        let ready = poll
+            .poll(Duration::from_millis(1))?
+            .iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        assert_eq!(ready, vec!["writer"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,273 @@
+//! Buffered reads on top of `Sources`.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::{interest, Events, Sources, Timeout};
+
+/// Polls sources, and reads from them into per-source buffers.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::os::unix::net::UnixStream;
+/// use std::time::Duration;
+///
+/// let (mut writer, reader) = UnixStream::pair()?;
+/// let mut pump = popol::Pump::new();
+///
+/// let nonblocking = pump.register("reader", &reader)?;
+/// writer.write_all(b"hello\n")?;
+/// drop(writer);
+///
+/// for (key, buf, closed) in pump.wait_timeout(Duration::from_secs(1))? {
+///     assert_eq!(key, &"reader");
+///     assert_eq!(buf.as_slice(), b"hello\n");
+///     assert!(closed);
+///
+///     // Consume the buffered data.
+///     buf.clear();
+/// }
+/// pump.unregister(&"reader");
+/// popol::set_nonblocking(&reader, nonblocking)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Pump<K> {
+    /// Sources to read from.
+    sources: Sources<K>,
+    /// Events buffer.
+    events: Events<K>,
+    /// Read buffers, one per source.
+    buffers: HashMap<K, Vec<u8>>,
+    /// Sources that were read from during the last wait, and whether they were closed.
+    ready: HashMap<K, bool>,
+    /// Maximum number of bytes read from a source on every wait.
+    limit: usize,
+}
+
+impl<K: Eq + Hash + Clone> Default for Pump<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone> Pump<K> {
+    /// Create a new pump, with no sources.
+    pub fn new() -> Self {
+        Self {
+            sources: Sources::new(),
+            events: Events::new(),
+            buffers: HashMap::new(),
+            ready: HashMap::new(),
+            limit: Self::DEFAULT_READ_LIMIT,
+        }
+    }
+
+    /// Default maximum number of bytes read from a source on every wait.
+    pub const DEFAULT_READ_LIMIT: usize = 64 * 1024;
+
+    /// Set the maximum number of bytes read from a source on every wait. This
+    /// prevents a fast source from growing its buffer without bound, or from
+    /// starving the other sources. Data that is left unread is read on the next
+    /// wait, which returns immediately.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the limit is zero.
+    pub fn set_read_limit(&mut self, limit: usize) {
+        assert!(limit > 0, "the read limit must not be zero");
+        self.limit = limit;
+    }
+
+    /// Register a source to read from, with the given key. The source must be kept
+    /// alive until it is unregistered.
+    ///
+    /// The source is put in non-blocking mode, and whether it was previously in
+    /// non-blocking mode is returned, so that its mode can be restored with
+    /// `set_nonblocking` once it is unregistered. If a source is already registered
+    /// under the given key, returns an error of kind `io::ErrorKind::AlreadyExists`,
+    /// and leaves both sources untouched.
+    pub fn register(&mut self, key: K, fd: &impl AsRawFd) -> io::Result<bool> {
+        if self.buffers.contains_key(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a source is already registered under this key",
+            ));
+        }
+        let nonblocking = crate::set_nonblocking(fd, true)?;
+
+        self.sources.register(key.clone(), fd, interest::READ);
+        self.buffers.insert(key, Vec::new());
+
+        Ok(nonblocking)
+    }
+
+    /// Unregister a source, and return its buffer, with any data that wasn't
+    /// consumed.
+    pub fn unregister(&mut self, key: &K) -> Option<Vec<u8>> {
+        self.sources.unregister(key);
+        self.ready.remove(key);
+        self.buffers.remove(key)
+    }
+
+    /// Get the read buffer of a source.
+    pub fn buffer_mut(&mut self, key: &K) -> Option<&mut Vec<u8>> {
+        self.buffers.get_mut(key)
+    }
+
+    /// Wait for sources to be readable, read available data into their buffers, up
+    /// to the read limit, and iterate over the sources that were read from, along
+    /// with their buffers.
+    /// The last tuple element is `true` if the source was closed, or had an error,
+    /// in which case it should be unregistered. Data is appended to the buffers, and
+    /// should be consumed, eg. with `Vec::drain`.
+    ///
+    /// If no source is ready within the given timeout, returns an error of kind
+    /// `io::ErrorKind::TimedOut`.
+    pub fn wait_timeout(
+        &mut self,
+        timeout: impl Into<Timeout>,
+    ) -> io::Result<impl Iterator<Item = (&K, &mut Vec<u8>, bool)>> {
+        self.sources.wait_timeout(&mut self.events, timeout)?;
+        self.ready.clear();
+
+        for (key, event) in self.events.iter() {
+            let buf = match self.buffers.get_mut(key) {
+                Some(buf) => buf,
+                None => continue,
+            };
+            let closed = event.is_err() || read(event.source.fd, buf, self.limit);
+
+            self.ready.insert(key.clone(), closed);
+        }
+        let ready = &self.ready;
+
+        Ok(self
+            .buffers
+            .iter_mut()
+            .filter_map(move |(key, buf)| ready.get(key).map(|closed| (key, buf, *closed))))
+    }
+}
+
+/// Read from a file descriptor until it would block, or `limit` bytes were read,
+/// and return whether the end of the file was reached, or an error occured.
+fn read(fd: RawFd, buf: &mut Vec<u8>, mut limit: usize) -> bool {
+    let mut chunk = [0; 4096];
+
+    while limit > 0 {
+        let len = chunk.len().min(limit);
+        let n = unsafe { libc::read(fd, chunk.as_mut_ptr() as *mut libc::c_void, len) };
+
+        if n > 0 {
+            buf.extend_from_slice(&chunk[..n as usize]);
+            limit -= n as usize;
+        } else if n == 0 {
+            return true;
+        } else {
+            return match io::Error::last_os_error().kind() {
+                io::ErrorKind::WouldBlock => false,
+                io::ErrorKind::Interrupted => continue,
+                _ => true,
+            };
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::prelude::*;
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    #[test]
+    fn test_pump() -> io::Result<()> {
+        let (mut writer0, reader0) = UnixStream::pair()?;
+        let (mut writer1, reader1) = UnixStream::pair()?;
+
+        let mut pump = Pump::new();
+
+        pump.register("reader0", &reader0)?;
+        pump.register("reader1", &reader1)?;
+
+        writer0.write_all(&[1; 8192])?;
+        writer1.write_all(b"hello")?;
+
+        let mut ready = pump
+            .wait_timeout(Duration::from_millis(1))?
+            .map(|(k, buf, closed)| (*k, buf.len(), closed))
+            .collect::<Vec<_>>();
+        ready.sort();
+        assert_eq!(ready, vec![("reader0", 8192, false), ("reader1", 5, false)]);
+
+        // Data that isn't consumed stays in the buffer.
+        writer1.write_all(b" world")?;
+        drop(writer1);
+
+        let ready = pump
+            .wait_timeout(Duration::from_millis(1))?
+            .map(|(k, buf, closed)| (*k, buf.clone(), closed))
+            .collect::<Vec<_>>();
+        assert_eq!(ready, vec![("reader1", b"hello world".to_vec(), true)]);
+
+        assert_eq!(pump.unregister(&"reader1"), Some(b"hello world".to_vec()));
+        assert_eq!(pump.buffer_mut(&"reader0").map(|b| b.len()), Some(8192));
+
+        let err = pump.wait_timeout(Duration::from_millis(1)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pump_register_twice() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let (_other_writer, other_reader) = UnixStream::pair()?;
+
+        let mut pump = Pump::new();
+
+        assert!(!pump.register("reader", &reader)?);
+        writer.write_all(b"hello")?;
+        assert_eq!(pump.wait_timeout(Duration::from_millis(1))?.count(), 1);
+
+        // The buffered data isn't lost, and the other source is left untouched.
+        let err = pump.register("reader", &other_reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert!(!crate::set_nonblocking(&other_reader, false)?);
+        assert_eq!(pump.buffer_mut(&"reader").unwrap().as_slice(), b"hello");
+
+        // The source is still in non-blocking mode, since it was registered.
+        assert_eq!(pump.unregister(&"reader"), Some(b"hello".to_vec()));
+        assert!(crate::set_nonblocking(&reader, false)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_pump_read_limit() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+
+        let mut pump = Pump::new();
+
+        pump.set_read_limit(1000);
+        pump.register("reader", &reader)?;
+        writer.write_all(&[1; 2500])?;
+
+        for len in [1000, 2000, 2500] {
+            let ready = pump
+                .wait_timeout(Duration::from_millis(1))?
+                .map(|(k, buf, closed)| (*k, buf.len(), closed))
+                .collect::<Vec<_>>();
+            assert_eq!(ready, vec![("reader", len, false)]);
+        }
+        let err = pump.wait_timeout(Duration::from_millis(1)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+}
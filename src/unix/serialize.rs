@@ -0,0 +1,84 @@
+//! Serialization of sources, with the `serde` feature.
+//!
+//! Only the keys, file descriptors and event interests of sources are serialized.
+//! File descriptors aren't meaningful outside of the process that opened them, so
+//! this is meant for persisting and reconstructing the intended set of sources,
+//! eg. after the files were re-opened under the same descriptors, not for
+//! transferring live sources.
+use std::hash::{BuildHasher, Hash};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{Source, Sources};
+
+/// Sources are serialized as a sequence of keys and sources, in polling order.
+/// The interests of disabled sources are the ones they will have once enabled.
+/// Other source settings, eg. whether a source is a waker, are not serialized.
+impl<K, S> Serialize for Sources<K, S>
+where
+    K: Eq + Hash + Clone + Serialize,
+    S: BuildHasher,
+{
+    fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        serializer.collect_seq(
+            self.events_snapshot()
+                .into_iter()
+                .zip(self.list.iter())
+                .map(|((key, events), source)| (key, Source::new(source.fd, events))),
+        )
+    }
+}
+
+/// Sources are deserialized from a sequence of keys and sources, as if registered
+/// in that order.
+impl<'de, K, S> Deserialize<'de> for Sources<K, S>
+where
+    K: Eq + Hash + Clone + Deserialize<'de>,
+    S: BuildHasher + Default,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<(K, Source)>::deserialize(deserializer)?;
+        let mut sources = Self::with_capacity_and_hasher(entries.len(), S::default());
+
+        for (key, source) in entries {
+            sources.insert(key, Source::new(source.fd, source.events));
+        }
+        Ok(sources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::os::unix::net::UnixStream;
+
+    use crate::{interest, Sources};
+
+    #[test]
+    fn test_serde() -> io::Result<()> {
+        let (writer, reader) = UnixStream::pair()?;
+        let mut sources = Sources::new();
+
+        sources.register("reader".to_owned(), &reader, interest::READ);
+        sources.register_disabled("writer".to_owned(), &writer, interest::WRITE);
+
+        let json = serde_json::to_string(&sources)?;
+        let restored: Sources<String> = serde_json::from_str(&json)?;
+
+        assert_eq!(restored.len(), 2);
+        assert_eq!(
+            restored.keys().cloned().collect::<Vec<_>>(),
+            vec!["reader", "writer"]
+        );
+        let reader = restored.get(&"reader".to_owned()).unwrap();
+        assert_eq!(reader.fd(), sources.get(&"reader".to_owned()).unwrap().fd());
+        assert_eq!(reader.events(), interest::READ);
+
+        let writer = restored.get(&"writer".to_owned()).unwrap();
+        assert_eq!(writer.fd(), sources.get(&"writer".to_owned()).unwrap().fd());
+        assert_eq!(writer.events(), interest::WRITE);
+        assert_eq!(writer.revents(), 0);
+
+        Ok(())
+    }
+}
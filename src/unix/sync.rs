@@ -0,0 +1,207 @@
+//! Sources that can be registered from other threads.
+use std::hash::Hash;
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+use crate::{Events, Interest, Sources, Timeout, Waker};
+
+/// A pending change to the sources, made through a registrar.
+enum Change<K> {
+    Register(K, RawFd, Interest),
+    Unregister(K),
+}
+
+/// Sources to poll, that can be registered and unregistered from other threads,
+/// through a `Registrar`, while a thread waits on them.
+///
+/// Changes made through a registrar are queued, and the set is woken up with a
+/// waker, so that the waiting thread applies them and polls again. The usual
+/// sequence is thus:
+///
+/// 1. The polling thread creates the set, and hands a registrar to other threads.
+/// 2. Another thread, eg. accepting connections, registers a source through its
+///    registrar, which wakes up the set.
+/// 3. The polling thread's `wait_timeout` returns with an event for the waker key,
+///    after having applied the change. The next wait polls the new source.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::prelude::*;
+/// use std::os::unix::net::UnixStream;
+/// use std::thread;
+///
+/// use popol::{interest, Events, SyncSources};
+///
+/// let (mut writer, reader) = UnixStream::pair()?;
+/// let mut sources = SyncSources::new("waker")?;
+/// let mut events = Events::new();
+/// let registrar = sources.registrar();
+///
+/// writer.write_all(b"hello")?;
+/// // Keep the source open while it is registered.
+/// let _reader = thread::spawn(move || {
+///     registrar.register("reader", &reader, interest::READ).unwrap();
+///     reader
+/// })
+/// .join()
+/// .unwrap();
+///
+/// sources.wait_timeout(&mut events, None)?;
+/// assert!(sources.sources().contains(&"reader"));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct SyncSources<K> {
+    /// Sources to poll.
+    sources: Sources<K>,
+    /// Key of the waker woken when changes are queued.
+    key: K,
+    /// Changes queued by registrars.
+    changes: Arc<Mutex<Vec<Change<K>>>>,
+    /// Waker woken when changes are queued.
+    waker: Arc<Waker>,
+}
+
+impl<K: Eq + Hash + Clone> SyncSources<K> {
+    /// Create a new set of sources, with a waker registered under the given key.
+    /// Events for that key are returned when changes were made by a registrar.
+    pub fn new(key: K) -> io::Result<Self> {
+        let mut sources = Sources::new();
+        let waker = Waker::new(&mut sources, key.clone())?;
+
+        Ok(Self {
+            sources,
+            key,
+            changes: Arc::new(Mutex::new(Vec::new())),
+            waker: Arc::new(waker),
+        })
+    }
+
+    /// Return a registrar, to register and unregister sources from other threads.
+    pub fn registrar(&self) -> Registrar<K> {
+        Registrar {
+            changes: self.changes.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+
+    /// Get the underlying sources, to modify them from the polling thread. Changes
+    /// queued by registrars are applied first.
+    pub fn sources(&mut self) -> &mut Sources<K> {
+        self.apply();
+        &mut self.sources
+    }
+
+    /// Wait for readiness events, like `Sources::wait_timeout`. Changes queued by
+    /// registrars are applied before polling, and after polling if the waker was
+    /// woken, in which case the waker is reset.
+    pub fn wait_timeout(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+    ) -> io::Result<()> {
+        self.apply();
+        self.sources.wait_timeout(events, timeout)?;
+
+        if events.iter().any(|(key, _)| key == &self.key) {
+            self.waker.snooze()?;
+            self.apply();
+        }
+        Ok(())
+    }
+
+    /// Apply the changes queued by registrars.
+    fn apply(&mut self) {
+        let changes = std::mem::take(&mut *self.changes.lock().unwrap());
+
+        for change in changes {
+            match change {
+                Change::Register(key, fd, events) => self.sources.register(key, &fd, events),
+                Change::Unregister(key) => {
+                    self.sources.unregister(&key);
+                }
+            }
+        }
+    }
+}
+
+/// A handle to register and unregister sources of a `SyncSources` from other
+/// threads. Changes are applied by the thread waiting on the sources, once woken.
+pub struct Registrar<K> {
+    changes: Arc<Mutex<Vec<Change<K>>>>,
+    waker: Arc<Waker>,
+}
+
+impl<K> Clone for Registrar<K> {
+    fn clone(&self) -> Self {
+        Self {
+            changes: self.changes.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<K> Registrar<K> {
+    /// Register a new source, like `Sources::register`, and wake up the sources.
+    /// The source must be kept open for as long as it is registered.
+    pub fn register(&self, key: K, fd: &impl AsRawFd, events: Interest) -> io::Result<()> {
+        self.push(Change::Register(key, fd.as_raw_fd(), events))
+    }
+
+    /// Unregister a source, like `Sources::unregister`, and wake up the sources.
+    pub fn unregister(&self, key: K) -> io::Result<()> {
+        self.push(Change::Unregister(key))
+    }
+
+    /// Queue a change, and wake up the sources.
+    fn push(&self, change: Change<K>) -> io::Result<()> {
+        self.changes.lock().unwrap().push(change);
+        self.waker.wake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::prelude::*;
+    use std::os::unix::net::UnixStream;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::interest;
+
+    #[test]
+    fn test_sync_sources() -> io::Result<()> {
+        let (mut writer, reader) = UnixStream::pair()?;
+        let mut sources = SyncSources::new("waker")?;
+        let mut events = Events::new();
+        let registrar = sources.registrar();
+
+        let handle = thread::spawn(move || {
+            registrar.register("reader", &reader, interest::READ)?;
+            Ok::<_, io::Error>(reader)
+        });
+
+        // Blocks until the other thread wakes up the sources.
+        sources.wait_timeout(&mut events, None)?;
+        let _reader = handle.join().unwrap()?;
+
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["waker"]);
+        assert!(sources.sources().contains(&"reader"));
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let keys = events.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["reader"], "the waker was reset");
+
+        sources.registrar().unregister("reader")?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert!(!sources.sources().contains(&"reader"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,180 @@
+//! Timers that can be polled, backed by a `timerfd`.
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::time::Duration;
+
+/// A timer that can be registered as a source, to wait for timers and I/O with the
+/// same `wait`. The timer is readable once it has expired, until its expirations
+/// are read with `read_expirations`.
+///
+/// Timers are backed by a `timerfd`, measured against the monotonic clock, and are
+/// only available on Linux.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use popol::{interest, Events, Sources, Timer};
+///
+/// let timer = Timer::new()?;
+/// let mut sources = Sources::new();
+/// let mut events = Events::new();
+///
+/// sources.register("timer", &timer, interest::READ);
+/// timer.set(Duration::from_millis(1))?;
+///
+/// sources.wait(&mut events)?;
+/// assert_eq!(timer.read_expirations()?, 1);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Timer {
+    fd: OwnedFd,
+}
+
+impl Timer {
+    /// Create a new timer, in non-blocking mode. The timer is disarmed until set.
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Arm the timer to expire once, after the given duration. A zero duration
+    /// expires as soon as possible. Replaces any previous setting.
+    pub fn set(&self, duration: Duration) -> io::Result<()> {
+        self.arm(duration, Duration::from_secs(0))
+    }
+
+    /// Arm the timer to expire periodically, every `period`, starting one period
+    /// from now. Replaces any previous setting.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the period is zero.
+    pub fn set_interval(&self, period: Duration) -> io::Result<()> {
+        assert!(
+            period > Duration::from_secs(0),
+            "the period must not be zero"
+        );
+
+        self.arm(period, period)
+    }
+
+    /// Disarm the timer, so that it no longer expires. Expirations that weren't
+    /// read yet are discarded.
+    pub fn cancel(&self) -> io::Result<()> {
+        self.settime(
+            timespec(Duration::from_secs(0)),
+            timespec(Duration::from_secs(0)),
+        )
+    }
+
+    /// Read the number of times the timer expired since it was last read, which
+    /// makes it no longer readable. Returns `0` if it didn't expire.
+    pub fn read_expirations(&self) -> io::Result<u64> {
+        super::snooze(self.fd.as_raw_fd())
+    }
+
+    fn arm(&self, value: Duration, interval: Duration) -> io::Result<()> {
+        // A zero value disarms the timer.
+        let value = value.max(Duration::from_nanos(1));
+
+        self.settime(timespec(value), timespec(interval))
+    }
+
+    fn settime(&self, value: libc::timespec, interval: libc::timespec) -> io::Result<()> {
+        let spec = libc::itimerspec {
+            it_interval: interval,
+            it_value: value,
+        };
+        let result =
+            unsafe { libc::timerfd_settime(self.fd.as_raw_fd(), 0, &spec, std::ptr::null_mut()) };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for Timer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+/// Convert a duration to a `timespec`, saturating at the largest number of seconds.
+fn timespec(duration: Duration) -> libc::timespec {
+    libc::timespec {
+        tv_sec: duration.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: duration.subsec_nanos() as libc::c_long,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use crate::{interest, Events, Sources};
+
+    #[test]
+    fn test_timer() -> io::Result<()> {
+        let timer = Timer::new()?;
+        let mut sources = Sources::new();
+        let mut events = Events::new();
+
+        sources.register("timer", &timer, interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut, "the timer is disarmed");
+
+        let start = Instant::now();
+        timer.set(Duration::from_millis(20))?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(
+            events.iter().map(|(k, _)| *k).collect::<Vec<_>>(),
+            vec!["timer"]
+        );
+        assert_eq!(timer.read_expirations()?, 1);
+        assert_eq!(timer.read_expirations()?, 0);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(40))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut, "the timer fires once");
+
+        timer.set_interval(Duration::from_millis(1))?;
+        std::thread::sleep(Duration::from_millis(10));
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert!(timer.read_expirations()? > 1);
+
+        timer.cancel()?;
+        assert_eq!(timer.read_expirations()?, 0);
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(5))
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            io::ErrorKind::TimedOut,
+            "the timer is cancelled"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,581 @@
+//! Backend for Windows, based on `WSAPoll`.
+//!
+//! Unlike `poll`, `WSAPoll` can only poll sockets: pipes, files and the console
+//! can't be registered, and sources are identified by their `SOCKET` handle. This
+//! backend only offers the core of the unix API, ie. registering sockets, waiting
+//! for readiness events and iterating over them. There are no wakers, since they
+//! are backed by pipes or `eventfd` on unix platforms.
+use std::io;
+use std::os::windows::io::{AsRawSocket, RawSocket};
+use std::time;
+
+use windows_sys::Win32::Networking::WinSock;
+
+pub use interest::Interest;
+
+/// Source readiness interest.
+pub mod interest {
+    use windows_sys::Win32::Networking::WinSock;
+
+    /// Events that can be waited for.
+    pub type Interest = WinSock::WSAPOLL_EVENT_FLAGS;
+
+    /// The associated socket is ready to be read, ie. it has normal data to be
+    /// read, or a pending connection to be accepted.
+    pub const READ: Interest = WinSock::POLLRDNORM;
+    /// The associated socket is ready to be written.
+    pub const WRITE: Interest = WinSock::POLLWRNORM;
+    /// The associated socket is ready to be read or written, ie. `READ | WRITE`.
+    pub const ALL: Interest = READ | WRITE;
+    /// Don't wait for any events.
+    ///
+    /// Note that a source registered with this interest is still polled: hangups,
+    /// errors and invalid sockets are always reported.
+    pub const NONE: Interest = 0x0;
+
+    // NOTE: `WSAPoll` fails with `WSAEINVAL` if it is asked to wait for priority
+    // data, ie. `POLLPRI`, which is why there is no equivalent of `interest::PRI`.
+}
+
+/// A readiness event.
+#[derive(Debug)]
+pub struct Event<'a> {
+    /// The socket is writable.
+    pub writable: bool,
+    /// The socket is readable.
+    pub readable: bool,
+    /// The socket has been disconnected.
+    pub hangup: bool,
+    /// An error has occured on the socket.
+    pub errored: bool,
+    /// The socket is not valid, eg. because it was closed while still registered.
+    pub invalid: bool,
+    /// The underlying source.
+    pub source: &'a Source,
+    /// Events returned by `WSAPoll`.
+    revents: Interest,
+}
+
+impl<'a> Event<'a> {
+    fn new(revents: Interest, source: &'a Source) -> Self {
+        Self {
+            readable: revents & interest::READ != 0,
+            writable: revents & interest::WRITE != 0,
+            hangup: revents & WinSock::POLLHUP != 0,
+            errored: revents & WinSock::POLLERR != 0,
+            invalid: revents & WinSock::POLLNVAL != 0,
+            source,
+            revents,
+        }
+    }
+
+    /// Return the events returned by `WSAPoll`, as a raw bitmask.
+    pub fn raw(&self) -> Interest {
+        self.revents
+    }
+}
+
+/// How long to wait for readiness events.
+///
+/// Timeouts are passed to `WSAPoll` in milliseconds, rounded up, like on unix
+/// platforms.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timeout {
+    /// Block until an event is returned.
+    Infinite,
+    /// Return immediately.
+    Immediate,
+    /// Wait for at most the given duration.
+    After(time::Duration),
+}
+
+impl Timeout {
+    /// Return the timeout in milliseconds, as passed to `WSAPoll`.
+    fn as_millis(&self) -> i32 {
+        match self {
+            Self::Infinite => -1,
+            Self::Immediate => 0,
+            Self::After(duration) => {
+                let mut ms = duration.as_millis();
+                if duration.subsec_nanos() % 1_000_000 != 0 {
+                    ms += 1;
+                }
+                ms.min(i32::MAX as u128) as i32
+            }
+        }
+    }
+}
+
+impl From<time::Duration> for Timeout {
+    fn from(duration: time::Duration) -> Self {
+        Self::After(duration)
+    }
+}
+
+impl From<Option<time::Duration>> for Timeout {
+    /// `None` blocks until an event is returned.
+    fn from(duration: Option<time::Duration>) -> Self {
+        duration.map_or(Self::Infinite, Self::After)
+    }
+}
+
+impl From<()> for Timeout {
+    fn from(_: ()) -> Self {
+        Self::Infinite
+    }
+}
+
+/// Readiness events returned by a wait.
+#[derive(Debug, Clone)]
+pub struct Events<K> {
+    /// Number of ready sources.
+    count: usize,
+    /// Keys of the sources polled.
+    index: Vec<K>,
+    /// Sources polled.
+    list: Vec<Source>,
+}
+
+impl<K: Eq + Clone> Default for Events<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Clone> Events<K> {
+    /// Create a new empty event tracker.
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            index: vec![],
+            list: vec![],
+        }
+    }
+
+    /// Create an empty event tracker of a certain capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            count: 0,
+            index: Vec::with_capacity(cap),
+            list: Vec::with_capacity(cap),
+        }
+    }
+
+    /// Iterate over ready sources and their keys.
+    pub fn iter(&self) -> Iter<'_, K> {
+        Iter {
+            index: &self.index,
+            list: &self.list,
+            pos: 0,
+        }
+    }
+
+    /// Check whether there are any events.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Return the number of ready sources.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Initialize the events list with sources.
+    fn initialize(&mut self, sources: &Sources<K>) {
+        self.count = 0;
+        self.index.clone_from(&sources.index);
+        self.list.clone_from(&sources.list);
+    }
+}
+
+impl<'a, K: Eq + Clone> IntoIterator for &'a Events<K> {
+    type Item = (&'a K, Event<'a>);
+    type IntoIter = Iter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over ready sources and their keys, returned by `Events::iter`.
+#[derive(Debug, Clone)]
+pub struct Iter<'a, K> {
+    /// Keys of the sources polled.
+    index: &'a [K],
+    /// Sources polled.
+    list: &'a [Source],
+    /// Number of sources iterated over.
+    pos: usize,
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = (&'a K, Event<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.list.len() {
+            let ix = self.pos;
+            let source = &self.list[ix];
+
+            self.pos += 1;
+
+            if source.revents != 0 {
+                return Some((&self.index[ix], Event::new(source.revents, source)));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.list.len() - self.pos))
+    }
+}
+
+/// A source of readiness events, ie. a socket such as a `net::TcpStream`.
+///
+/// Only sockets can be sources on Windows.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Source {
+    fd: WinSock::SOCKET,
+    events: Interest,
+    revents: Interest,
+}
+
+// Sources are passed to `WSAPoll` as `WSAPOLLFD` structures, so their layouts must match.
+const _: [(); std::mem::size_of::<WinSock::WSAPOLLFD>()] = [(); std::mem::size_of::<Source>()];
+const _: [(); std::mem::align_of::<WinSock::WSAPOLLFD>()] = [(); std::mem::align_of::<Source>()];
+
+impl Source {
+    /// Create a new source from a raw socket, waiting for the given events.
+    pub const fn new(socket: RawSocket, events: Interest) -> Self {
+        Self {
+            fd: socket as WinSock::SOCKET,
+            events,
+            revents: 0,
+        }
+    }
+
+    /// Return the raw socket of this source.
+    pub fn socket(&self) -> RawSocket {
+        self.fd as RawSocket
+    }
+
+    /// Return the events to wait for on this source.
+    pub fn events(&self) -> Interest {
+        self.events
+    }
+
+    /// Return the events that occured on this source, as reported by `WSAPoll`.
+    pub fn revents(&self) -> Interest {
+        self.revents
+    }
+
+    /// Set events to wait for on this source.
+    pub fn set(&mut self, events: Interest) {
+        self.events |= events;
+    }
+
+    /// Unset events to wait for on this source.
+    pub fn unset(&mut self, events: Interest) {
+        self.events &= !events;
+    }
+}
+
+impl AsRawSocket for Source {
+    fn as_raw_socket(&self) -> RawSocket {
+        self.socket()
+    }
+}
+
+impl PartialEq for Source {
+    fn eq(&self, other: &Self) -> bool {
+        self.fd == other.fd && self.events == other.events
+    }
+}
+
+impl Eq for Source {}
+
+/// A set of sockets to poll, identified by keys of type `K`.
+///
+/// Sources are looked up by key with a linear search, which is fast enough for
+/// the number of sockets `WSAPoll` is suited to.
+#[derive(Debug, Clone)]
+pub struct Sources<K> {
+    /// Keys of the registered sources, in polling order.
+    index: Vec<K>,
+    /// Registered sources, in polling order.
+    list: Vec<Source>,
+}
+
+impl<K: Eq + Clone> Default for Sources<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Clone> Sources<K> {
+    /// Creates a new set of sources to poll.
+    pub fn new() -> Self {
+        Self {
+            index: vec![],
+            list: vec![],
+        }
+    }
+
+    /// Creates a new set of sources to poll, with the given capacity.
+    pub fn with_capacity(cap: usize) -> Self {
+        Self {
+            index: Vec::with_capacity(cap),
+            list: Vec::with_capacity(cap),
+        }
+    }
+
+    /// Return the number of registered sources.
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    /// Return whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// Register a new socket, with the given key, and wait for the specified events.
+    ///
+    /// The socket must be kept open for as long as it is registered. If a source
+    /// is already registered under the given key, it is replaced.
+    pub fn register(&mut self, key: K, socket: &impl AsRawSocket, events: Interest) {
+        let source = Source::new(socket.as_raw_socket(), events);
+
+        if let Some(ix) = self.find(&key) {
+            self.list[ix] = source;
+        } else {
+            self.index.push(key);
+            self.list.push(source);
+        }
+    }
+
+    /// Unregister a source, given its key, and return it. Returns `None` if no
+    /// source is registered under the given key.
+    pub fn unregister(&mut self, key: &K) -> Option<Source> {
+        let ix = self.find(key)?;
+
+        self.index.swap_remove(ix);
+
+        Some(self.list.swap_remove(ix))
+    }
+
+    /// Set event interests on a source identified by its key, in addition to the
+    /// ones already set. Returns `false` if the source was not found.
+    pub fn set(&mut self, key: &K, events: Interest) -> bool {
+        if let Some(ix) = self.find(key) {
+            self.list[ix].set(events);
+            return true;
+        }
+        false
+    }
+
+    /// Unset event interests on a source, keeping the others. Returns `false` if
+    /// the source was not found.
+    pub fn unset(&mut self, key: &K, events: Interest) -> bool {
+        if let Some(ix) = self.find(key) {
+            self.list[ix].unset(events);
+            return true;
+        }
+        false
+    }
+
+    /// Get a source by key.
+    pub fn get(&self, key: &K) -> Option<&Source> {
+        self.find(key).map(|ix| &self.list[ix])
+    }
+
+    /// Get a source by key, mutably.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut Source> {
+        self.find(key).map(move |ix| &mut self.list[ix])
+    }
+
+    /// Wait for readiness events on the given list of sources. If no event
+    /// is returned within the given timeout, returns an error of kind `io::ErrorKind::TimedOut`.
+    ///
+    /// If there are no sources to poll, returns immediately without events, since
+    /// `WSAPoll` rejects an empty list.
+    pub fn wait_timeout(
+        &mut self,
+        events: &mut Events<K>,
+        timeout: impl Into<Timeout>,
+    ) -> Result<(), io::Error> {
+        events.initialize(self);
+
+        if events.list.is_empty() {
+            return Ok(());
+        }
+        let result = poll(&mut events.list, timeout.into().as_millis())?;
+
+        if result == 0 {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        events.count = result;
+
+        Ok(())
+    }
+
+    /// Wait for readiness events on the given list of sources. If the wait is
+    /// interrupted, it is resumed.
+    pub fn wait(&mut self, events: &mut Events<K>) -> Result<(), io::Error> {
+        self.wait_timeout(events, Timeout::Infinite)
+    }
+
+    /// Find the position of the source registered under the given key.
+    fn find(&self, key: &K) -> Option<usize> {
+        self.index.iter().position(|k| k == key)
+    }
+}
+
+/// Call `WSAPoll` on the given sources, resuming the wait if it is interrupted,
+/// and return the number of ready sources.
+///
+/// Winsock must have been initialized with `WSAStartup`, which the standard
+/// library does when it creates a socket.
+fn poll(list: &mut [Source], timeout: i32) -> io::Result<usize> {
+    loop {
+        let result = unsafe {
+            WinSock::WSAPoll(
+                list.as_mut_ptr() as *mut WinSock::WSAPOLLFD,
+                list.len() as u32,
+                timeout,
+            )
+        };
+
+        if result != WinSock::SOCKET_ERROR {
+            return Ok(result as usize);
+        }
+        let err = io::Error::from_raw_os_error(unsafe { WinSock::WSAGetLastError() });
+
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::prelude::*;
+    use std::net;
+    use std::time::Duration;
+
+    /// Return a connected pair of TCP sockets.
+    fn pair() -> io::Result<(net::TcpStream, net::TcpStream)> {
+        let listener = net::TcpListener::bind("127.0.0.1:0")?;
+        let client = net::TcpStream::connect(listener.local_addr()?)?;
+        let (server, _) = listener.accept()?;
+
+        Ok((client, server))
+    }
+
+    #[test]
+    fn test_readable() -> io::Result<()> {
+        let (mut writer, reader) = pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        writer.write_all(&[1])?;
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+        assert_eq!(events.len(), 1);
+
+        let (key, event) = events.iter().next().unwrap();
+        assert_eq!(*key, "reader");
+        assert!(event.readable && !event.writable);
+        assert_eq!(event.source.socket(), reader.as_raw_socket());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_writable() -> io::Result<()> {
+        let (writer, _reader) = pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("writer", &writer, interest::WRITE);
+        sources.wait(&mut events)?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.writable && !event.readable);
+
+        // No longer waiting for the socket to be writable.
+        assert!(sources.unset(&"writer", interest::WRITE));
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hangup() -> io::Result<()> {
+        let (writer, reader) = pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        drop(writer);
+        sources.wait_timeout(&mut events, Duration::from_secs(1))?;
+
+        let (_, event) = events.iter().next().unwrap();
+        assert!(event.hangup || event.readable);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unregister() -> io::Result<()> {
+        let (mut writer, reader) = pair()?;
+        let (_, other) = pair()?;
+
+        let mut events = Events::new();
+        let mut sources = Sources::new();
+
+        sources.register("reader", &reader, interest::READ);
+        sources.register("other", &other, interest::READ);
+        assert_eq!(sources.len(), 2);
+
+        let source = sources.unregister(&"reader").unwrap();
+        assert_eq!(source.socket(), reader.as_raw_socket());
+        assert!(sources.get(&"reader").is_none());
+        assert!(sources.unregister(&"reader").is_none());
+
+        writer.write_all(&[1])?;
+        let err = sources
+            .wait_timeout(&mut events, Duration::from_millis(1))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wait_empty() -> io::Result<()> {
+        let mut sources = Sources::<&str>::new();
+        let mut events = Events::new();
+
+        // Would block forever if the empty set was polled.
+        sources.wait(&mut events)?;
+        assert!(events.is_empty());
+
+        Ok(())
+    }
+}
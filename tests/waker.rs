@@ -0,0 +1,28 @@
+//! Tests that count the file descriptors opened by the process. They are kept out
+//! of the unit tests, which open file descriptors concurrently.
+#![cfg(target_os = "linux")]
+use std::fs;
+use std::io;
+
+use popol::{Sources, Waker};
+
+/// Return the number of open file descriptors of the process.
+fn open_fds() -> io::Result<usize> {
+    Ok(fs::read_dir("/proc/self/fd")?.count())
+}
+
+#[test]
+fn test_waker_eventfd() -> io::Result<()> {
+    let mut sources = Sources::new();
+
+    let before = open_fds()?;
+    let waker = Waker::new(&mut sources, "waker")?;
+
+    // An `eventfd` waker only needs a single file descriptor.
+    assert_eq!(open_fds()?, before + 1);
+
+    drop(waker);
+    assert_eq!(open_fds()?, before);
+
+    Ok(())
+}